@@ -0,0 +1,15 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use r2d2_mongodb::connstring::{parse, parse_host};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Err(e) = parse(s) {
+            e.render();
+        }
+        if let Err(e) = parse_host(s) {
+            e.render();
+        }
+    }
+});
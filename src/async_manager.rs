@@ -0,0 +1,119 @@
+//! Async pool managers for bb8 and deadpool.
+//!
+//! The driver is blocking, so connecting and validating run on tokio's blocking
+//! thread pool and are driven from the async pool.
+use r2d2::ManageConnection;
+
+use tokio::task::{spawn_blocking, JoinError};
+
+use std::io;
+use std::panic;
+use std::sync::Arc;
+
+use crate::error::Error;
+use crate::trace::Span;
+use crate::{ConnectionOptions, MongoConnection, MongodbConnectionManager, OptionsHandle};
+
+/// Struct for managing a pool of MongoDB connections from async code
+///
+/// Shares `ConnectionOptions` and connection semantics with `MongodbConnectionManager`.
+#[derive(Clone)]
+pub struct AsyncMongodbConnectionManager {
+    inner: Arc<MongodbConnectionManager>,
+}
+
+impl AsyncMongodbConnectionManager {
+    pub fn new(options: ConnectionOptions) -> AsyncMongodbConnectionManager {
+        MongodbConnectionManager::new(options).into()
+    }
+
+    pub fn new_with_uri(uri: &str) -> Result<AsyncMongodbConnectionManager, Error> {
+        MongodbConnectionManager::new_with_uri(uri).map(Into::into)
+    }
+
+    /// A handle to replace the options while the manager is in use.
+    pub fn options_handle(&self) -> OptionsHandle {
+        self.inner.options_handle()
+    }
+
+    async fn connect(&self) -> Result<MongoConnection, Error> {
+        let inner = self.inner.clone();
+        // Keeps the caller's span as the parent of spans on the blocking thread.
+        let span = Span::current();
+        spawn_blocking(move || span.in_scope(|| inner.connect()))
+            .await
+            .map_err(|e| Error::Connect(map_join_error(e)))?
+    }
+
+    async fn is_valid(&self, conn: &mut MongoConnection) -> Result<(), Error> {
+        if self.inner.is_retired(conn) {
+            return Err(Error::Retired(conn.host.clone()));
+        }
+        if !self.inner.validates {
+            return Ok(());
+        }
+        let inner = self.inner.clone();
+        let client = conn.client.clone();
+        let host = conn.host.clone();
+        let span = Span::current();
+        spawn_blocking(move || span.in_scope(|| inner.validate(&client, &host)))
+            .await
+            .map_err(|e| Error::Validation(map_join_error(e)))?
+    }
+}
+
+impl From<MongodbConnectionManager> for AsyncMongodbConnectionManager {
+    fn from(manager: MongodbConnectionManager) -> AsyncMongodbConnectionManager {
+        AsyncMongodbConnectionManager {
+            inner: Arc::new(manager),
+        }
+    }
+}
+
+#[cfg(feature = "bb8")]
+impl bb8::ManageConnection for AsyncMongodbConnectionManager {
+    type Connection = MongoConnection;
+    type Error = Error;
+
+    async fn connect(&self) -> Result<Self::Connection, Error> {
+        AsyncMongodbConnectionManager::connect(self).await
+    }
+
+    async fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Error> {
+        AsyncMongodbConnectionManager::is_valid(self, conn).await
+    }
+
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        ManageConnection::has_broken(&*self.inner, conn)
+    }
+}
+
+#[cfg(feature = "deadpool")]
+impl deadpool::managed::Manager for AsyncMongodbConnectionManager {
+    type Type = MongoConnection;
+    type Error = Error;
+
+    async fn create(&self) -> Result<Self::Type, Error> {
+        self.connect().await
+    }
+
+    async fn recycle(
+        &self,
+        conn: &mut Self::Type,
+        _metrics: &deadpool::managed::Metrics,
+    ) -> deadpool::managed::RecycleResult<Error> {
+        if ManageConnection::has_broken(&*self.inner, conn) {
+            return Err(deadpool::managed::RecycleError::Backend(Error::Retired(conn.host.clone())));
+        }
+        self.is_valid(conn).await?;
+        Ok(())
+    }
+}
+
+// Re-raises panics from the blocking task; otherwise the runtime is shutting down.
+fn map_join_error(e: JoinError) -> mongodb::error::Error {
+    if e.is_panic() {
+        panic::resume_unwind(e.into_panic());
+    }
+    io::Error::new(io::ErrorKind::Interrupted, e.to_string()).into()
+}
@@ -0,0 +1,116 @@
+//! Per-host circuit breaker for connection creation.
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::Host;
+
+/// Settings for failing fast on hosts that keep failing
+///
+/// After `failure_threshold` consecutive connect or validation failures, a host is not
+/// tried for `cool_down`. Then a single probe connection is created and validated; the
+/// host is closed again if it succeeds and reopened if it fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the circuit for a host
+    ///
+    /// Default: `5`
+    pub failure_threshold: u32,
+    /// How long an open host is skipped before it is probed
+    ///
+    /// Default: `30s`
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> CircuitBreaker {
+        CircuitBreaker {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What a caller may do with a host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Attempt {
+    Allowed,
+    Probe,
+    Rejected,
+}
+
+enum HostState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen,
+}
+
+pub(crate) struct Breaker {
+    config: CircuitBreaker,
+    hosts: Mutex<HashMap<(String, u16), HostState>>,
+}
+
+impl Breaker {
+    pub(crate) fn new(config: CircuitBreaker) -> Breaker {
+        Breaker {
+            config,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `host` would be allowed or probed, without reserving the probe.
+    pub(crate) fn is_available(&self, host: &Host) -> bool {
+        match self.lock().get(&key(host)) {
+            None | Some(HostState::Closed { .. }) => true,
+            Some(HostState::Open { until }) => Instant::now() >= *until,
+            Some(HostState::HalfOpen) => false,
+        }
+    }
+
+    /// Whether `host` is open and still cooling down.
+    pub(crate) fn is_open(&self, host: &Host) -> bool {
+        matches!(self.lock().get(&key(host)), Some(HostState::Open { until }) if Instant::now() < *until)
+    }
+
+    /// Reserves an attempt on `host`; only one caller gets the probe of an open host.
+    pub(crate) fn acquire(&self, host: &Host) -> Attempt {
+        let mut hosts = self.lock();
+        let state = hosts.entry(key(host)).or_insert(HostState::Closed { failures: 0 });
+        match *state {
+            HostState::Closed { .. } => Attempt::Allowed,
+            HostState::Open { until } if Instant::now() >= until => {
+                *state = HostState::HalfOpen;
+                Attempt::Probe
+            }
+            HostState::Open { .. } | HostState::HalfOpen => Attempt::Rejected,
+        }
+    }
+
+    pub(crate) fn record_success(&self, host: &Host) {
+        self.lock().insert(key(host), HostState::Closed { failures: 0 });
+    }
+
+    pub(crate) fn record_failure(&self, host: &Host) {
+        let mut hosts = self.lock();
+        let state = hosts.entry(key(host)).or_insert(HostState::Closed { failures: 0 });
+        let failures = match *state {
+            HostState::Closed { failures } => failures + 1,
+            HostState::Open { .. } | HostState::HalfOpen => self.config.failure_threshold,
+        };
+        *state = if failures >= self.config.failure_threshold {
+            HostState::Open {
+                until: Instant::now() + self.config.cool_down,
+            }
+        } else {
+            HostState::Closed { failures }
+        };
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<(String, u16), HostState>> {
+        self.hosts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn key(host: &Host) -> (String, u16) {
+    (host.hostname.clone(), host.port)
+}
@@ -1,13 +1,24 @@
 //! Connection string parsing and options.
-use mongodb::error::{Error, ErrorKind::ArgumentError};
+use crate::error::{Component, Error, OptionIssue, ParseError, ParseWarning};
+use crate::uri_options;
 
 use std::collections::BTreeMap;
+use std::net::Ipv6Addr;
 
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = std::result::Result<T, ParseError>;
 
 pub const DEFAULT_PORT: u16 = 27017;
-pub const URI_SCHEME: &'static str = "mongodb://";
+pub const URI_SCHEME: &str = "mongodb://";
+
+/// How `parse_with_mode` treats questionable options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParseMode {
+    /// Duplicate, unknown, deprecated and empty options are errors.
+    Strict,
+    /// Questionable options are accepted and reported as warnings.
+    Lenient,
+}
 
 /// Encapsulates the hostname and port of a host.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -24,8 +35,8 @@ impl Host {
     // Creates a new Host struct.
     fn new(host_name: String, port: u16) -> Host {
         Host {
-            host_name: host_name,
-            port: port,
+            host_name,
+            port,
             ipc: String::new(),
         }
     }
@@ -34,7 +45,7 @@ impl Host {
         Host {
             host_name: String::new(),
             port: DEFAULT_PORT,
-            ipc: ipc,
+            ipc,
         }
     }
 
@@ -44,10 +55,15 @@ impl Host {
 }
 
 /// Encapsulates the options and read preference tags of a MongoDB connection.
+///
+/// Known options are keyed by their canonical spelling, with deprecated names such as
+/// `ssl` stored under their replacement (`tls`).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ConnectionOptions {
     pub options: BTreeMap<String, String>,
     pub read_pref_tags: Vec<String>,
+    /// Deprecated option names used in the connection string, as written
+    pub deprecated: Vec<String>,
 }
 
 impl ConnectionOptions {
@@ -57,14 +73,15 @@ impl ConnectionOptions {
         read_pref_tags: Vec<String>,
     ) -> ConnectionOptions {
         ConnectionOptions {
-            options: options,
-            read_pref_tags: read_pref_tags,
+            options,
+            read_pref_tags,
+            deprecated: Vec::new(),
         }
     }
 
-    // Helper method to retrieve an option from the map.
+    // Helper method to retrieve an option from the map, by any spelling or alias.
     pub fn get(&self, key: &str) -> Option<&String> {
-        self.options.get(key)
+        self.options.get(uri_options::canonical(key).unwrap_or(key))
     }
 }
 
@@ -98,18 +115,248 @@ impl ConnectionString {
             options: None,
         }
     }
+
+    /// Creates a builder for a connection string, with no hosts and database "test".
+    pub fn builder() -> ConnectionStringBuilder {
+        let mut cs = ConnectionString::with_host(Host::new(String::new(), DEFAULT_PORT));
+        cs.hosts.clear();
+        ConnectionStringBuilder(cs)
+    }
+
+    /// Formats the connection string as a URI that `parse` reads back into the same value.
+    ///
+    /// The URI includes the password.
+    pub fn to_uri(&self) -> String {
+        let mut uri = String::from(URI_SCHEME);
+
+        if let Some(ref user) = self.user {
+            uri.push_str(user);
+            uri.push(':');
+            uri.push_str(self.password.as_ref().map_or("", String::as_str));
+            uri.push('@');
+        }
+
+        let hosts: Vec<String> = self
+            .hosts
+            .iter()
+            .map(|host| {
+                if host.has_ipc() {
+                    host.ipc.clone()
+                } else if host.host_name.contains(':') {
+                    format!("[{}]:{}", host.host_name, host.port)
+                } else {
+                    format!("{}:{}", host.host_name, host.port)
+                }
+            })
+            .collect();
+        uri.push_str(&hosts.join(","));
+
+        uri.push('/');
+        if let Some(ref database) = self.database {
+            uri.push_str(database);
+            if let Some(ref collection) = self.collection {
+                uri.push('.');
+                uri.push_str(collection);
+            }
+        }
+
+        if let Some(ref options) = self.options {
+            let pairs: Vec<String> = options
+                .options
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .chain(options.read_pref_tags.iter().map(|tags| format!("readPreferenceTags={}", tags)))
+                .collect();
+            if !pairs.is_empty() {
+                uri.push('?');
+                uri.push_str(&pairs.join("&"));
+            }
+        }
+
+        uri
+    }
+}
+
+/// Builder for `ConnectionString`
+///
+/// Host names are lowercased, as `parse` does, and socket hosts are listed after TCP
+/// hosts. Credentials are percent-encoded and stored that way, as in a parsed string.
+pub struct ConnectionStringBuilder(ConnectionString);
+
+impl ConnectionStringBuilder {
+    pub fn with_host(&mut self, host_name: &str, port: u16) -> &mut ConnectionStringBuilder {
+        self.0.hosts.push(Host::new(host_name.to_ascii_lowercase(), port));
+        self
+    }
+
+    pub fn with_ipv6_host(&mut self, address: Ipv6Addr, port: u16) -> &mut ConnectionStringBuilder {
+        self.0.hosts.push(Host::new(address.to_string(), port));
+        self
+    }
+
+    /// Adds a Unix domain socket host; the path must end in `.sock`.
+    pub fn with_socket(&mut self, path: &str) -> &mut ConnectionStringBuilder {
+        self.0.hosts.push(Host::with_ipc(path.to_ascii_lowercase()));
+        self
+    }
+
+    pub fn with_auth(&mut self, user: &str, password: &str) -> &mut ConnectionStringBuilder {
+        self.0.user = Some(urlencoding::encode(user));
+        self.0.password = Some(urlencoding::encode(password));
+        self
+    }
+
+    pub fn with_database(&mut self, database: &str) -> &mut ConnectionStringBuilder {
+        self.0.database = Some(String::from(database));
+        self
+    }
+
+    pub fn with_collection(&mut self, collection: &str) -> &mut ConnectionStringBuilder {
+        self.0.collection = Some(String::from(collection));
+        self
+    }
+
+    /// Sets an option; deprecated names are stored under their replacement.
+    pub fn with_option(&mut self, key: &str, value: &str) -> &mut ConnectionStringBuilder {
+        let key = uri_options::canonical(key).map_or_else(|| String::from(key), String::from);
+        self.options().options.insert(key, String::from(value));
+        self
+    }
+
+    pub fn with_read_preference_tags(&mut self, tags: &str) -> &mut ConnectionStringBuilder {
+        self.options().read_pref_tags.push(String::from(tags));
+        self
+    }
+
+    /// Validates the connection string and records its URI form in `string`.
+    pub fn build(&self) -> std::result::Result<ConnectionString, Error> {
+        let mut cs = self.0.clone();
+        // Socket paths are found by their `.sock` suffix, which only works at the end
+        cs.hosts.sort_by_key(Host::has_ipc);
+        validate(&cs).map_err(Error::Config)?;
+        cs.string = Some(cs.to_uri());
+        Ok(cs)
+    }
+
+    fn options(&mut self) -> &mut ConnectionOptions {
+        self.0
+            .options
+            .get_or_insert_with(|| ConnectionOptions::new(BTreeMap::new(), Vec::new()))
+    }
+}
+
+// Checks that every part of a built connection string survives `parse`.
+fn validate(cs: &ConnectionString) -> std::result::Result<(), String> {
+    const RESERVED: &[char] = &[',', '/', '?', '@', '&', ';', '#', '[', ']'];
+
+    if cs.user.as_ref().is_some_and(String::is_empty) {
+        return Err(String::from("The empty string is not a valid username."));
+    }
+    if cs.hosts.is_empty() {
+        return Err(String::from("At least one host is required."));
+    }
+    for host in &cs.hosts {
+        if host.has_ipc() {
+            if !host.ipc.ends_with(".sock") {
+                return Err(format!("Socket path '{}' must end in '.sock'.", host.ipc));
+            }
+            if host.ipc.contains([',', '?', '@', ':']) {
+                return Err(format!("Socket path '{}' contains reserved characters.", host.ipc));
+            }
+        } else if host.host_name.parse::<Ipv6Addr>().is_err()
+            && (host.host_name.is_empty() || host.host_name.contains(|c| c == ':' || RESERVED.contains(&c)))
+        {
+            return Err(format!("Invalid host name '{}'.", host.host_name));
+        }
+    }
+
+    if let Some(ref database) = cs.database {
+        if database.is_empty() || database.contains(|c| c == '.' || c == '$' || c == ' ' || RESERVED.contains(&c)) {
+            return Err(format!("Invalid database name '{}'.", database));
+        }
+    } else if cs.collection.is_some() {
+        return Err(String::from("A collection requires a database."));
+    }
+    if let Some(ref collection) = cs.collection {
+        if collection.is_empty() || collection.contains(|c| RESERVED.contains(&c)) {
+            return Err(format!("Invalid collection name '{}'.", collection));
+        }
+    }
+
+    if let Some(ref options) = cs.options {
+        for (key, value) in &options.options {
+            if key.is_empty() || key.contains('=') || key.contains(|c| RESERVED.contains(&c)) {
+                return Err(format!("Invalid option name '{}'.", key));
+            }
+            if value.is_empty() || value.contains(['&', ';', '#']) {
+                return Err(format!("Invalid value '{}' for option '{}'.", value, key));
+            }
+        }
+        if options.read_pref_tags.iter().any(|tags| tags.contains(['&', ';', '#'])) {
+            return Err(String::from("Read preference tags contain reserved characters."));
+        }
+    }
+
+    // `parse` treats any `.sock` as the end of a socket path
+    let sockets = cs.hosts.iter().filter(|host| host.has_ipc()).count();
+    if cs.to_uri().matches(".sock").count() > sockets {
+        return Err(String::from("'.sock' may only appear at the end of socket paths."));
+    }
+
+    Ok(())
 }
 
 /// Parses a MongoDB connection string URI as defined by
 /// [the manual](http://docs.mongodb.org/manual/reference/connection-string/).
+///
+/// Questionable options are accepted silently; use `parse_with_mode` to inspect them.
 pub fn parse(address: &str) -> Result<ConnectionString> {
+    parse_with_mode(address, ParseMode::Lenient).map(|(cs, _)| cs)
+}
+
+/// Parses a MongoDB connection string URI, treating duplicate, unknown, deprecated and
+/// empty options according to `mode`.
+///
+/// In `Lenient` mode those options are returned as warnings alongside the parsed string.
+pub fn parse_with_mode(address: &str, mode: ParseMode) -> Result<(ConnectionString, Vec<ParseWarning>)> {
+    let (cs, mut warnings) = parse_uri(address).map_err(|e| e.with_input(redact(address)))?;
+    if mode == ParseMode::Strict && !warnings.is_empty() {
+        return Err(ParseError::from(warnings.remove(0)).with_input(redact(address)));
+    }
+    Ok((cs, warnings))
+}
+
+/// Masks the password of a connection string, keeping every other byte in place.
+pub fn redact(address: &str) -> String {
+    let addr = match address.strip_prefix(URI_SCHEME) {
+        Some(addr) => addr,
+        None => return String::from(address),
+    };
+    let host_str = partition(addr, "/").0;
+    let secret = if host_str.contains('@') {
+        let (user_info, _) = rpartition(host_str, "@");
+        rpartition(user_info, ":").1
+    } else if addr.contains('@') {
+        // An unescaped '/' in the credentials ends the host list early, so everything
+        // up to the last '@' may be part of the password.
+        rpartition(addr, "@").0
+    } else {
+        return String::from(address);
+    };
+
+    let start = offset_in(address, secret);
+    let end = start + secret.len();
+    format!("{}{}{}", &address[..start], "*".repeat(secret.len()), &address[end..])
+}
+
+fn parse_uri(address: &str) -> Result<(ConnectionString, Vec<ParseWarning>)> {
     if !address.starts_with(URI_SCHEME) {
-        return Err(ArgumentError {
-            message: String::from(
-                "MongoDB connection string must start with 'mongodb://'.",
-            )
-        }
-        .into());
+        let scheme_end = address.find("://").map_or(address.len(), |idx| idx + 3);
+        return Err(ParseError::new(
+            Component::Scheme,
+            0..scheme_end,
+            "MongoDB connection string must start with 'mongodb://'.",
+        ));
     }
 
     // Remove scheme
@@ -121,88 +368,94 @@ pub fn parse(address: &str) -> Result<ConnectionString> {
     let mut database: Option<String> = Some(String::from("test"));
     let mut collection: Option<String> = None;
     let mut options: Option<ConnectionOptions> = None;
+    let mut warnings: Vec<ParseWarning> = Vec::new();
 
     // Split on host/path
     let (host_str, path_str) = if addr.contains(".sock") {
         // Partition ipc socket
         let (host_part, path_part) = rsplit(addr, ".sock");
-        if path_part.starts_with('/') {
-            (host_part, &path_part[1..])
-        } else {
-            (host_part, path_part)
-        }
+        (host_part, path_part.strip_prefix('/').unwrap_or(path_part))
     } else {
         // Partition standard format
         partition(addr, "/")
     };
 
     if path_str.is_empty() && host_str.contains('?') {
-        return Err(ArgumentError {
-            message: String::from(
-                "A '/' is required between the host list and any options.",
-            )
-        }
-        .into());
+        let idx = offset_in(address, host_str) + host_str.find('?').unwrap_or(0);
+        return Err(ParseError::new(
+            Component::Hosts,
+            idx..idx + 1,
+            "A '/' is required between the host list and any options.",
+        ));
     }
 
     // Split on authentication and hosts
     if host_str.contains('@') {
         let (user_info, host_string) = rpartition(host_str, "@");
-        let (u, p) = parse_user_info(user_info)?;
+        let (u, p) = parse_user_info(user_info)
+            .map_err(|e| e.offset_by(offset_in(address, user_info)))?;
         user = Some(String::from(u));
         password = Some(String::from(p));
-        hosts = split_hosts(host_string)?;
+        hosts = split_hosts(host_string)
+            .map_err(|e| e.offset_by(offset_in(address, host_string)))?;
     } else {
-        hosts = split_hosts(host_str)?;
+        hosts = split_hosts(host_str)
+            .map_err(|e| e.offset_by(offset_in(address, host_str)))?;
     }
 
     let mut opts = "";
 
     // Split on database name, collection, and options
     if !path_str.is_empty() {
-        if path_str.starts_with('?') {
-            opts = &path_str[1..];
+        if let Some(stripped) = path_str.strip_prefix('?') {
+            opts = stripped;
         } else {
             let (dbase, options) = partition(path_str, "?");
             let (dbase_new, coll) = partition(dbase, ".");
             database = Some(String::from(dbase_new));
-            collection = Some(String::from(coll));
+            if !coll.is_empty() {
+                collection = Some(String::from(coll));
+            }
             opts = options;
         }
     }
 
     // Collect options if any exist
     if !opts.is_empty() {
-        options = Some(split_options(opts).unwrap());
+        let base = offset_in(address, opts);
+        let (opts, opt_warnings) = split_options(opts).map_err(|e| e.offset_by(base))?;
+        options = Some(opts);
+        warnings = opt_warnings.into_iter().map(|w| w.offset_by(base)).collect();
     }
 
-    Ok(ConnectionString {
-        hosts: hosts,
+    let cs = ConnectionString {
+        hosts,
         string: Some(String::from(address)),
-        user: user,
-        password: password,
-        database: database,
-        collection: collection,
-        options: options,
-    })
+        user,
+        password,
+        database,
+        collection,
+        options,
+    };
+    Ok((cs, warnings))
 }
 
 // Parse user information of the form user:password
 fn parse_user_info(user_info: &str) -> Result<(&str, &str)> {
     let (user, password) = rpartition(user_info, ":");
-    if user_info.contains('@') || user.contains(':') {
-        return Err(ArgumentError {
-            message: String::from(
-                "':' or '@' characters in a username or password must be escaped according to RFC 2396.",
-            )
-        }
-        .into());
+    if let Some(idx) = user_info.find('@').or_else(|| user.find(':')) {
+        return Err(ParseError::new(
+            Component::Credentials,
+            idx..idx + 1,
+            "':' or '@' characters in a username or password must be escaped according to RFC 2396.",
+        ));
     }
     if user.is_empty() {
-        return Err(ArgumentError {
-            message: String::from("The empty string is not a valid username."),
-        }
-        .into());
+        return Err(ParseError::new(
+            Component::Credentials,
+            0..0,
+            "The empty string is not a valid username.",
+        ));
     }
     Ok((user, password))
 }
@@ -216,18 +469,32 @@ fn parse_ipv6_literal_host(entity: &str) -> Result<Host> {
                     let port = &entity[idx + 2..];
                     match port.parse::<u16>() {
                         Ok(val) => Ok(Host::new(entity[1..idx].to_ascii_lowercase(), val)),
-                        Err(_) => Err(ArgumentError{ message: String::from("Port must be an integer.")}.into()),
+                        Err(_) => Err(ParseError::new(
+                            Component::Hosts,
+                            idx + 2..entity.len(),
+                            "Port must be an integer.",
+                        )),
                     }
                 }
-                None => Ok(Host::new(entity[1..].to_ascii_lowercase(), DEFAULT_PORT)),
+                None if entity.ends_with(']') => {
+                    Ok(Host::new(entity[1..entity.len() - 1].to_ascii_lowercase(), DEFAULT_PORT))
+                }
+                None => {
+                    let idx = entity.find(']').map_or(entity.len(), |idx| idx + 1);
+                    Err(ParseError::new(
+                        Component::Hosts,
+                        idx..entity.len(),
+                        "An IPv6 address literal may only be followed by ':' and a port.",
+                    ))
+                }
             }
         }
         None => {
-            Err(ArgumentError{
-                message: String::from(
-                    "An IPv6 address must be enclosed in '[' and ']' according to RFC 2732.",
-                )
-            }.into())
+            Err(ParseError::new(
+                Component::Hosts,
+                0..entity.len(),
+                "An IPv6 address must be enclosed in '[' and ']' according to RFC 2732.",
+            ))
         }
     }
 }
@@ -235,26 +502,32 @@ fn parse_ipv6_literal_host(entity: &str) -> Result<Host> {
 // Parses a host entity of the form host or host:port, and redirects IPv6 entities.
 // All host names are lowercased.
 pub fn parse_host(entity: &str) -> Result<Host> {
+    parse_host_entity(entity).map_err(|e| e.with_input(String::from(entity)))
+}
+
+fn parse_host_entity(entity: &str) -> Result<Host> {
     if entity.starts_with('[') {
         // IPv6 host
         parse_ipv6_literal_host(entity)
     } else if entity.contains(':') {
         // Common host:port format
         let (host, port) = partition(entity, ":");
-        if port.contains(':') {
-            return Err(ArgumentError {
-                message: String::from(
-                    "Reserved characters such as ':' must
+        if let Some(idx) = port.find(':') {
+            return Err(ParseError::new(
+                Component::Hosts,
+                offset_in(entity, port) + idx..offset_in(entity, port) + idx + 1,
+                "Reserved characters such as ':' must
                         be escaped according to RFC 2396. An IPv6 address literal
                         must be enclosed in '[' and according to RFC 2732.",
-                )
-            }.into());
+            ));
         }
         match port.parse::<u16>() {
             Ok(val) => Ok(Host::new(host.to_ascii_lowercase(), val)),
-            Err(_) => Err(ArgumentError {
-                message: String::from("Port must be an unsigned integer."),
-            }.into()),
+            Err(_) => Err(ParseError::new(
+                Component::Hosts,
+                offset_in(entity, port)..entity.len(),
+                "Port must be an unsigned integer.",
+            )),
         }
     } else if entity.contains(".sock") {
         // IPC socket
@@ -270,20 +543,28 @@ fn split_hosts(host_str: &str) -> Result<Vec<Host>> {
     let mut hosts: Vec<Host> = Vec::new();
     for entity in host_str.split(',') {
         if entity.is_empty() {
-            return Err(ArgumentError {
-                message: String::from("Empty host, or extra comma in host list."),
-            }.into());
+            let idx = offset_in(host_str, entity);
+            return Err(ParseError::new(
+                Component::Hosts,
+                idx..idx,
+                "Empty host, or extra comma in host list.",
+            ));
         }
-        let host = parse_host(entity)?;
+        let host = parse_host_entity(entity).map_err(|e| e.offset_by(offset_in(host_str, entity)))?;
         hosts.push(host);
     }
     Ok(hosts)
 }
 
-// Parses the delimited string into its options and Read Preference Tags.
-fn parse_options(opts: &str, delim: Option<&str>) -> ConnectionOptions {
+// Parses the delimited string into its options and Read Preference Tags, normalizing
+// option names and noting questionable options along the way.
+fn parse_options(opts: &str, delim: Option<&str>) -> Result<(ConnectionOptions, Vec<ParseWarning>)> {
     let mut options = BTreeMap::new();
     let mut read_pref_tags = Vec::new();
+    let mut deprecated = Vec::new();
+    let mut warnings = Vec::new();
+    // Lowercased option name -> (name as first written, name stored in `options`)
+    let mut seen: BTreeMap<String, (&str, String)> = BTreeMap::new();
 
     // Split and collect options into a vec
     let opt_list = match delim {
@@ -294,48 +575,102 @@ fn parse_options(opts: &str, delim: Option<&str>) -> ConnectionOptions {
     // Build the map and tag vec
     for opt in opt_list {
         let (key, val) = partition(opt, "=");
-        if key.to_ascii_lowercase() == "readpreferencetags" {
+        let start = offset_in(opts, opt);
+        let mut warn = |issue| {
+            warnings.push(ParseWarning {
+                issue,
+                key: String::from(key),
+                span: start..start + opt.len(),
+            })
+        };
+
+        if let Some(replacement) = uri_options::replacement(key) {
+            warn(OptionIssue::Deprecated { replacement });
+            deprecated.push(String::from(key));
+        } else if !uri_options::is_known(key) {
+            warn(OptionIssue::Unknown);
+        }
+
+        // An empty tag set is meaningful: it matches any server.
+        if key.eq_ignore_ascii_case("readpreferencetags") {
             read_pref_tags.push(String::from(val));
         } else {
-            options.insert(String::from(key), String::from(val));
+            if val.is_empty() {
+                warn(OptionIssue::EmptyValue);
+            }
+
+            let name = uri_options::canonical(key).map_or_else(|| String::from(key), String::from);
+            let folded = name.to_ascii_lowercase();
+            let name = match seen.get(&folded) {
+                // The same option under another name, e.g. `ssl` and `tls`
+                Some((previous, stored)) if !previous.eq_ignore_ascii_case(key) => {
+                    if options.get(stored).map(String::as_str) != Some(val) {
+                        return Err(ParseError::new(
+                            Component::Options,
+                            start..start + opt.len(),
+                            format!(
+                                "Options '{}' and '{}' both set '{}' but to different values.",
+                                previous, key, stored
+                            ),
+                        ));
+                    }
+                    stored.clone()
+                }
+                Some((_, stored)) => {
+                    warn(OptionIssue::Duplicate);
+                    stored.clone()
+                }
+                None => {
+                    seen.insert(folded, (key, name.clone()));
+                    name
+                }
+            };
+            options.insert(name, String::from(val));
         }
     }
 
-    ConnectionOptions::new(options, read_pref_tags)
+    let mut options = ConnectionOptions::new(options, read_pref_tags);
+    options.deprecated = deprecated;
+    Ok((options, warnings))
 }
 
 // Determines the option delimiter and offloads parsing to parse_options.
-fn split_options(opts: &str) -> Result<ConnectionOptions> {
+fn split_options(opts: &str) -> Result<(ConnectionOptions, Vec<ParseWarning>)> {
     let and_idx = opts.find('&');
     let semi_idx = opts.find(';');
     let mut delim = None;
 
-    if and_idx != None && semi_idx != None {
-        return Err(ArgumentError {
-            message: String::from(
-                "Cannot mix '&' and ';' for option separators.",
-            )
-        }.into());
-    } else if and_idx != None {
+    if let (Some(and_idx), Some(semi_idx)) = (and_idx, semi_idx) {
+        return Err(ParseError::new(
+            Component::Options,
+            and_idx.max(semi_idx)..and_idx.max(semi_idx) + 1,
+            "Cannot mix '&' and ';' for option separators.",
+        ));
+    } else if and_idx.is_some() {
         delim = Some("&");
-    } else if semi_idx != None {
+    } else if semi_idx.is_some() {
         delim = Some(";");
-    } else if opts.find('=') == None {
-        return Err(ArgumentError {
-            message: String::from(
-                "InvalidURI: MongoDB URI options are key=value pairs.",
-            )
-        }.into());
-    }
-    let options = parse_options(opts, delim);
-    Ok(options)
+    } else if !opts.contains('=') {
+        return Err(ParseError::new(
+            Component::Options,
+            0..opts.len(),
+            "InvalidURI: MongoDB URI options are key=value pairs.",
+        ));
+    }
+    parse_options(opts, delim)
+}
+
+// Byte offset of `part` within `whole`; `part` must be a subslice of `whole`.
+fn offset_in(whole: &str, part: &str) -> usize {
+    part.as_ptr() as usize - whole.as_ptr() as usize
 }
 
 // Partitions a string around the left-most occurrence of the separator, if it exists.
+// Both halves are always slices of `string`, so `offset_in` can locate them.
 fn partition<'a>(string: &'a str, sep: &str) -> (&'a str, &'a str) {
     match string.find(sep) {
         Some(idx) => (&string[..idx], &string[idx + sep.len()..]),
-        None => (string, ""),
+        None => (string, &string[string.len()..]),
     }
 }
 
@@ -343,7 +678,7 @@ fn partition<'a>(string: &'a str, sep: &str) -> (&'a str, &'a str) {
 fn rpartition<'a>(string: &'a str, sep: &str) -> (&'a str, &'a str) {
     match string.rfind(sep) {
         Some(idx) => (&string[..idx], &string[idx + sep.len()..]),
-        None => (string, ""),
+        None => (string, &string[string.len()..]),
     }
 }
 
@@ -351,6 +686,6 @@ fn rpartition<'a>(string: &'a str, sep: &str) -> (&'a str, &'a str) {
 fn rsplit<'a>(string: &'a str, sep: &str) -> (&'a str, &'a str) {
     match string.rfind(sep) {
         Some(idx) => (&string[..idx + sep.len()], &string[idx + sep.len()..]),
-        None => (string, ""),
+        None => (string, &string[string.len()..]),
     }
 }
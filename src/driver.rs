@@ -0,0 +1,211 @@
+//! Adapter over the supported MongoDB driver generations.
+//!
+//! Everything that touches driver APIs which changed between releases lives here, so the
+//! rest of the crate only deals with `ConnectionOptions` and the types re-exported below.
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Document};
+use mongodb::error::ErrorKind;
+use mongodb::event::sdam::TopologyDescription;
+use mongodb::options::{ClientOptions, Credential, ServerAddress, Tls, TlsOptions};
+use mongodb::ServerType;
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::trace;
+use crate::{ConnectionOptions, Host, SSLConfig, VerifyPeer};
+
+pub use mongodb::sync::{Client, Database};
+
+/// The replica set state reported by the driver's monitoring; standalone servers report
+/// no set, primary or election.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct Topology {
+    set_name: Option<String>,
+    primary: Option<String>,
+    election_id: Option<ObjectId>,
+    reachable: bool,
+}
+
+impl Topology {
+    fn from_description(description: &TopologyDescription) -> Topology {
+        let servers = description.servers();
+        Topology {
+            set_name: description.set_name().cloned(),
+            primary: servers
+                .iter()
+                .find(|(_, server)| server.server_type() == ServerType::RsPrimary)
+                .map(|(address, _)| address.to_string()),
+            election_id: description.max_election_id(),
+            reachable: servers.values().any(|server| server.server_type() != ServerType::Unknown),
+        }
+    }
+}
+
+/// Follows a client's topology through the driver's monitoring events, which arrive from
+/// its background heartbeats, so checking for changes needs no I/O.
+#[derive(Debug, Default)]
+pub(crate) struct TopologyWatch {
+    // The first topology with a reachable server, and the latest one.
+    state: Mutex<(Option<Topology>, Option<Topology>)>,
+}
+
+impl TopologyWatch {
+    fn observe(&self, description: &TopologyDescription) {
+        let topology = Topology::from_description(description);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        if state.0.is_none() && topology.reachable {
+            state.0 = Some(topology.clone());
+        }
+        state.1 = Some(topology);
+    }
+
+    /// Whether the primary, election or reachability changed since the server was first
+    /// reached.
+    pub(crate) fn changed(&self) -> bool {
+        match *self.state.lock().unwrap_or_else(|e| e.into_inner()) {
+            (Some(ref first), Some(ref latest)) => first != latest,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "mongodb-3")]
+fn watch_topology(client_options: &mut ClientOptions, watch: Arc<TopologyWatch>) {
+    use mongodb::event::sdam::SdamEvent;
+    use mongodb::event::EventHandler;
+
+    client_options.sdam_event_handler = Some(EventHandler::callback(move |event| {
+        if let SdamEvent::TopologyDescriptionChanged(event) = event {
+            watch.observe(&event.new_description);
+        }
+    }));
+}
+
+#[cfg(not(feature = "mongodb-3"))]
+fn watch_topology(client_options: &mut ClientOptions, watch: Arc<TopologyWatch>) {
+    use mongodb::event::sdam::{SdamEventHandler, TopologyDescriptionChangedEvent};
+
+    struct Handler(Arc<TopologyWatch>);
+
+    impl SdamEventHandler for Handler {
+        fn handle_topology_description_changed_event(&self, event: TopologyDescriptionChangedEvent) {
+            self.0.observe(&event.new_description);
+        }
+    }
+
+    client_options.sdam_event_handler = Some(Arc::new(Handler(watch)));
+}
+
+/// Creates a client for `host` along with the configured database handle and a watch on
+/// its topology.
+///
+/// With `options.handshake`, also connects and authenticates, giving up after
+/// `handshake_timeout` if one is given.
+pub(crate) fn connect(
+    options: &ConnectionOptions,
+    host: &Host,
+    handshake_timeout: Option<Duration>,
+) -> Result<(Client, Database, Arc<TopologyWatch>), Error> {
+    let mut client_options = ClientOptions::default();
+    client_options.hosts = vec![ServerAddress::Tcp {
+        host: host.hostname.clone(),
+        port: Some(host.port),
+    }];
+    client_options.connect_timeout = options.connect_timeout;
+    client_options.server_selection_timeout = options.connect_timeout;
+    client_options.heartbeat_freq = options.heartbeat_frequency;
+    client_options.tls = options.ssl.as_ref().map(tls);
+
+    if let Some(ref auth) = options.auth {
+        let mut credential = Credential::default();
+        credential.username = Some(auth.username.clone());
+        credential.password = Some(auth.password.clone());
+        client_options.credential = Some(credential);
+    }
+
+    // Installed before the client exists, so its first heartbeat is seen.
+    let watch = Arc::new(TopologyWatch::default());
+    watch_topology(&mut client_options, watch.clone());
+
+    let client = Client::with_options(client_options).map_err(|e| classify(e, Error::Connect))?;
+    let db = client.database_with_options(&options.db, options.database_options());
+    // Running any command makes the driver connect, negotiate TLS and authenticate.
+    if options.handshake {
+        let span = trace::handshake(options);
+        let result = span.in_scope(|| ping(&client, handshake_timeout));
+        trace::outcome(&span, &result);
+        result?;
+    }
+    Ok((client, db, watch))
+}
+
+// The driver has no per-operation timeout, so a bounded ping runs on its own thread and
+// is abandoned when the time is up; it ends with the client's own timeouts.
+fn ping(client: &Client, timeout: Option<Duration>) -> Result<(), Error> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return run_admin_command(client, doc! { "ping": 1 }).map(drop).map_err(|e| classify(e, Error::Connect)),
+    };
+    let (done, finished) = mpsc::channel();
+    let client = client.clone();
+    thread::spawn(move || {
+        let _ = done.send(run_admin_command(&client, doc! { "ping": 1 }));
+    });
+    match finished.recv_timeout(timeout) {
+        Ok(result) => result.map(drop).map_err(|e| classify(e, Error::Connect)),
+        Err(_) => Err(Error::Connect(
+            io::Error::new(io::ErrorKind::TimedOut, "handshake did not finish within the pool timeout").into(),
+        )),
+    }
+}
+
+#[cfg(feature = "mongodb-3")]
+fn run_admin_command(client: &Client, command: Document) -> mongodb::error::Result<Document> {
+    client.database("admin").run_command(command).run()
+}
+
+#[cfg(not(feature = "mongodb-3"))]
+fn run_admin_command(client: &Client, command: Document) -> mongodb::error::Result<Document> {
+    client.database("admin").run_command(command, None)
+}
+
+/// Round-trips to the server to check that the client is still usable.
+#[cfg(feature = "mongodb-3")]
+pub(crate) fn validate(client: &Client) -> Result<(), Error> {
+    client
+        .list_database_names()
+        .run()
+        .map_err(|e| classify(e, Error::Validation))?;
+    Ok(())
+}
+
+/// Round-trips to the server to check that the client is still usable.
+#[cfg(not(feature = "mongodb-3"))]
+pub(crate) fn validate(client: &Client) -> Result<(), Error> {
+    client
+        .list_database_names(None, None)
+        .map_err(|e| classify(e, Error::Validation))?;
+    Ok(())
+}
+
+// Reports credential failures as `Error::Auth`, anything else through `other`.
+fn classify(e: mongodb::error::Error, other: fn(mongodb::error::Error) -> Error) -> Error {
+    match *e.kind {
+        ErrorKind::Authentication { .. } => Error::Auth(e),
+        _ => other(e),
+    }
+}
+
+fn tls(ssl: &SSLConfig) -> Tls {
+    let mut tls_options = TlsOptions::default();
+    tls_options.ca_file_path = ssl.ca_file.as_ref().map(PathBuf::from);
+    tls_options.cert_key_file_path = ssl.cert.as_ref().map(|cert| PathBuf::from(&cert.key_file));
+    tls_options.allow_invalid_certificates = Some(ssl.verify_peer == VerifyPeer::No);
+    Tls::Enabled(tls_options)
+}
@@ -0,0 +1,224 @@
+//! Connection options from environment variables.
+use std::env::{self, VarError};
+use std::fs;
+
+use crate::connstring::parse_host;
+use crate::error::{EnvVarError, Error};
+use crate::{Auth, ConnectionOptions, Host, SSLCert, SSLConfig, VerifyPeer};
+
+impl ConnectionOptions {
+    /// Creates options from environment variables named `<prefix>_<NAME>`.
+    ///
+    /// `<prefix>_URI` is parsed as a connection string; the other variables override
+    /// what it sets:
+    ///
+    /// | Variable                   | Value                                   |
+    /// |----------------------------|-----------------------------------------|
+    /// | `<prefix>_HOSTS`           | Comma-separated `host[:port]` list      |
+    /// | `<prefix>_DB`              | Database name                           |
+    /// | `<prefix>_USERNAME`        | Username                                |
+    /// | `<prefix>_PASSWORD`        | Password                                |
+    /// | `<prefix>_PASSWORD_FILE`   | File holding the password               |
+    /// | `<prefix>_TLS`             | `true` or `false`                       |
+    /// | `<prefix>_TLS_CA_FILE`     | CA certificate file                     |
+    /// | `<prefix>_TLS_CERT_FILE`   | Client certificate, with `TLS_KEY_FILE` |
+    /// | `<prefix>_TLS_KEY_FILE`    | Client key, with `TLS_CERT_FILE`        |
+    /// | `<prefix>_TLS_VERIFY_PEER` | `true` (default) or `false`             |
+    ///
+    /// Setting any `TLS_*` variable other than `TLS=false` enables TLS. Every malformed
+    /// variable is reported in the returned `Error::Env`.
+    pub fn from_env(prefix: &str) -> Result<ConnectionOptions, Error> {
+        let mut reader = EnvReader {
+            prefix,
+            errors: Vec::new(),
+        };
+        let options = reader.read();
+        if reader.errors.is_empty() {
+            Ok(options)
+        } else {
+            Err(Error::Env(reader.errors))
+        }
+    }
+}
+
+struct EnvReader<'a> {
+    prefix: &'a str,
+    errors: Vec<EnvVarError>,
+}
+
+impl<'a> EnvReader<'a> {
+    fn read(&mut self) -> ConnectionOptions {
+        let mut options = match self.var("URI") {
+            Some(uri) => ConnectionOptions::from_uri(&uri).unwrap_or_else(|e| {
+                self.error("URI", e.to_string());
+                ConnectionOptions::default()
+            }),
+            None => ConnectionOptions::default(),
+        };
+
+        match self.var("HOSTS") {
+            Some(hosts) => options.hosts = self.hosts(&hosts),
+            None if options.hosts.is_empty() && !self.failed("URI") => {
+                self.error("HOSTS", format!("either {} or this must be set", self.name("URI")))
+            }
+            None => {}
+        }
+
+        if let Some(db) = self.var("DB") {
+            if db.is_empty() {
+                self.error("DB", "must not be empty");
+            } else {
+                options.db = db;
+            }
+        }
+
+        self.read_auth(&mut options);
+        self.read_tls(&mut options);
+        options
+    }
+
+    fn hosts(&mut self, hosts: &str) -> Vec<Host> {
+        let mut parsed = Vec::new();
+        for entity in hosts.split(',').map(str::trim) {
+            if entity.is_empty() {
+                self.error("HOSTS", "empty host, or extra comma in host list");
+                continue;
+            }
+            match parse_host(entity) {
+                Ok(ref host) if host.has_ipc() => {
+                    self.error("HOSTS", format!("socket host '{}' is not supported", entity))
+                }
+                Ok(host) => parsed.push(Host {
+                    hostname: host.host_name,
+                    port: host.port,
+                }),
+                Err(e) => self.error("HOSTS", format!("'{}': {}", entity, e.message)),
+            }
+        }
+        parsed
+    }
+
+    fn read_auth(&mut self, options: &mut ConnectionOptions) {
+        let password = match (self.var("PASSWORD"), self.var("PASSWORD_FILE")) {
+            (Some(_), Some(_)) => {
+                let message = format!("cannot be combined with {}", self.name("PASSWORD"));
+                self.error("PASSWORD_FILE", message);
+                None
+            }
+            (Some(password), None) => Some(password),
+            (None, Some(path)) => match fs::read_to_string(&path) {
+                Ok(password) => Some(password.trim_end_matches(['\n', '\r']).to_string()),
+                Err(e) => {
+                    self.error("PASSWORD_FILE", format!("cannot read '{}': {}", path, e));
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+
+        match (self.var("USERNAME"), password, options.auth.as_mut()) {
+            (Some(username), Some(password), _) => options.auth = Some(Auth { username, password }),
+            (Some(username), None, Some(auth)) => auth.username = username,
+            (None, Some(password), Some(auth)) => auth.password = password,
+            (Some(_), None, None) if !self.failed("PASSWORD_FILE") => {
+                let message = format!(
+                    "requires {} or {}",
+                    self.name("PASSWORD"),
+                    self.name("PASSWORD_FILE")
+                );
+                self.error("USERNAME", message)
+            }
+            (None, Some(_), None) => {
+                let message = format!("requires {}", self.name("USERNAME"));
+                self.error("PASSWORD", message)
+            }
+            _ => {}
+        }
+    }
+
+    fn read_tls(&mut self, options: &mut ConnectionOptions) {
+        let enabled = self.var("TLS").and_then(|tls| self.bool("TLS", &tls));
+        let ca_file = self.var("TLS_CA_FILE");
+        let cert_file = self.var("TLS_CERT_FILE");
+        let key_file = self.var("TLS_KEY_FILE");
+        let verify_peer = self
+            .var("TLS_VERIFY_PEER")
+            .and_then(|verify| self.bool("TLS_VERIFY_PEER", &verify));
+
+        let configured = ca_file.is_some() || cert_file.is_some() || key_file.is_some() || verify_peer.is_some();
+        if enabled == Some(false) {
+            options.ssl = None;
+            return;
+        }
+        if enabled != Some(true) && !configured {
+            return;
+        }
+
+        let ssl = options.ssl.get_or_insert_with(SSLConfig::default);
+        if ca_file.is_some() {
+            ssl.ca_file = ca_file;
+        }
+        match (cert_file, key_file) {
+            (Some(certificate_file), Some(key_file)) => {
+                ssl.cert = Some(SSLCert {
+                    certificate_file,
+                    key_file,
+                })
+            }
+            (Some(_), None) => {
+                let message = format!("requires {}", self.name("TLS_KEY_FILE"));
+                self.error("TLS_CERT_FILE", message)
+            }
+            (None, Some(_)) => {
+                let message = format!("requires {}", self.name("TLS_CERT_FILE"));
+                self.error("TLS_KEY_FILE", message)
+            }
+            (None, None) => {}
+        }
+        if let Some(verify_peer) = verify_peer {
+            ssl.verify_peer = if verify_peer { VerifyPeer::Yes } else { VerifyPeer::No };
+        }
+    }
+
+    fn bool(&mut self, name: &str, value: &str) -> Option<bool> {
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Some(true),
+            "false" | "0" | "no" => Some(false),
+            _ => {
+                self.error(name, format!("'{}' is not a boolean", value));
+                None
+            }
+        }
+    }
+
+    fn var(&mut self, name: &str) -> Option<String> {
+        match env::var(self.name(name)) {
+            Ok(value) => Some(value),
+            Err(VarError::NotPresent) => None,
+            Err(VarError::NotUnicode(_)) => {
+                self.error(name, "is not valid unicode");
+                None
+            }
+        }
+    }
+
+    fn name(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}_{}", self.prefix, name)
+        }
+    }
+
+    fn failed(&self, name: &str) -> bool {
+        let variable = self.name(name);
+        self.errors.iter().any(|e| e.variable == variable)
+    }
+
+    fn error<S: Into<String>>(&mut self, name: &str, message: S) {
+        self.errors.push(EnvVarError {
+            variable: self.name(name),
+            message: message.into(),
+        });
+    }
+}
@@ -0,0 +1,46 @@
+//! Connection lifecycle events.
+//!
+//! `MongodbConnectionManager` reports what happens to the connections it creates; r2d2's
+//! `HandleEvent` reports what the pool does with them (checkouts, timeouts, releases).
+//! Registering both gives a complete picture.
+use std::fmt;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::Host;
+
+/// A trait which is provided with information about connection lifecycle events.
+///
+/// All methods do nothing by default.
+pub trait ConnectionEventHandler: fmt::Debug + Sync + Send {
+    /// Called before connecting to `host`.
+    fn on_connect_start(&self, host: &Host) {
+        let _ = host;
+    }
+
+    /// Called when a connection to `host` has been created.
+    fn on_connect_success(&self, host: &Host, duration: Duration) {
+        let _ = (host, duration);
+    }
+
+    /// Called when connecting to `host` failed, or was skipped by the circuit breaker.
+    fn on_connect_failure(&self, host: &Host, error: &Error) {
+        let _ = (host, error);
+    }
+
+    /// Called when a connection to `host` failed its health check.
+    fn on_validation_failure(&self, host: &Host, error: &Error) {
+        let _ = (host, error);
+    }
+
+    /// Called when a connection to `host` is found broken and will be dropped by the pool.
+    fn on_discard(&self, host: &Host) {
+        let _ = host;
+    }
+}
+
+/// A `ConnectionEventHandler` which ignores all events.
+#[derive(Copy, Clone, Debug)]
+pub struct NopConnectionEventHandler;
+
+impl ConnectionEventHandler for NopConnectionEventHandler {}
@@ -0,0 +1,226 @@
+//! Connection manager wrapper that fails on demand.
+use r2d2::ManageConnection;
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::MongoConnection;
+
+/// Failure produced by a `FaultInjectingManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectedFault {
+    /// A scripted `connect` failure
+    Connect,
+    /// A scripted `is_valid` failure
+    Validation,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InjectedFault::Connect => f.write_str("injected connect failure"),
+            InjectedFault::Validation => f.write_str("injected validation failure"),
+        }
+    }
+}
+
+impl error::Error for InjectedFault {}
+
+impl From<InjectedFault> for Error {
+    fn from(fault: InjectedFault) -> Error {
+        let e = io::Error::new(io::ErrorKind::ConnectionRefused, fault.to_string()).into();
+        match fault {
+            InjectedFault::Connect => Error::Connect(e),
+            InjectedFault::Validation => Error::Validation(e),
+        }
+    }
+}
+
+/// Wraps a connection manager so that tests can make it fail
+///
+/// Faults are programmed through a `FaultHandle`, which stays usable after the manager
+/// is moved into a pool:
+///
+/// ```rust
+/// # extern crate r2d2_mongodb;
+/// # use r2d2_mongodb::test_util::FaultInjectingManager;
+/// # use r2d2_mongodb::{ConnectionOptions, MongodbConnectionManager};
+/// let options = ConnectionOptions::builder().with_host("localhost", 27017).build();
+/// let manager = FaultInjectingManager::new(MongodbConnectionManager::new(options));
+/// let faults = manager.handle();
+/// faults.fail_connects(3);
+/// ```
+pub struct FaultInjectingManager<M: ManageConnection> {
+    inner: M,
+    faults: FaultHandle<M::Connection>,
+}
+
+/// Programs the faults of a `FaultInjectingManager`
+///
+/// Clones control the same manager.
+pub struct FaultHandle<C> {
+    faults: Arc<Mutex<Faults<C>>>,
+}
+
+type Predicate<C> = Box<dyn Fn(&C) -> bool + Send + Sync>;
+
+struct Faults<C> {
+    failed_connects: usize,
+    latency: Option<Duration>,
+    valid: Option<bool>,
+    broken: Option<bool>,
+    failing: Vec<Predicate<C>>,
+    failing_hosts: HashSet<(String, u16)>,
+}
+
+impl<M: ManageConnection> FaultInjectingManager<M> {
+    pub fn new(inner: M) -> FaultInjectingManager<M> {
+        FaultInjectingManager {
+            inner,
+            faults: FaultHandle {
+                faults: Arc::new(Mutex::new(Faults {
+                    failed_connects: 0,
+                    latency: None,
+                    valid: None,
+                    broken: None,
+                    failing: Vec::new(),
+                    failing_hosts: HashSet::new(),
+                })),
+            },
+        }
+    }
+
+    /// A handle to program this manager's faults.
+    pub fn handle(&self) -> FaultHandle<M::Connection> {
+        self.faults.clone()
+    }
+
+    /// The wrapped manager.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+}
+
+impl<C> FaultHandle<C> {
+    /// Fails the next `count` connects, without calling the wrapped manager.
+    pub fn fail_connects(&self, count: usize) {
+        self.lock().failed_connects = count;
+    }
+
+    /// Delays every connect and validation by `latency`.
+    pub fn set_latency(&self, latency: Option<Duration>) {
+        self.lock().latency = latency;
+    }
+
+    /// Forces the result of `is_valid`; `None` defers to the wrapped manager.
+    pub fn set_valid(&self, valid: Option<bool>) {
+        self.lock().valid = valid;
+    }
+
+    /// Forces the result of `has_broken`; `None` defers to the wrapped manager.
+    pub fn set_broken(&self, broken: Option<bool>) {
+        self.lock().broken = broken;
+    }
+
+    /// Discards new connections for which `predicate` holds and fails the connect.
+    ///
+    /// Adds to the predicates given before.
+    pub fn fail_connections_matching<F>(&self, predicate: F)
+    where
+        F: Fn(&C) -> bool + Send + Sync + 'static,
+    {
+        self.lock().failing.push(Box::new(predicate));
+    }
+
+    /// Removes all faults.
+    pub fn clear(&self) {
+        let mut faults = self.lock();
+        faults.failed_connects = 0;
+        faults.latency = None;
+        faults.valid = None;
+        faults.broken = None;
+        faults.failing.clear();
+        faults.failing_hosts.clear();
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Faults<C>> {
+        self.faults.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn delay(&self) {
+        let latency = self.lock().latency;
+        if let Some(latency) = latency {
+            thread::sleep(latency);
+        }
+    }
+}
+
+impl FaultHandle<MongoConnection> {
+    /// Fails every connection made to `hostname:port`, in addition to the hosts failed
+    /// before.
+    pub fn fail_host(&self, hostname: &str, port: u16) {
+        if !self.lock().failing_hosts.insert((hostname.to_string(), port)) {
+            return;
+        }
+        let hostname = hostname.to_string();
+        self.fail_connections_matching(move |conn: &MongoConnection| {
+            conn.host().hostname == hostname && conn.host().port == port
+        });
+    }
+}
+
+impl<C> Clone for FaultHandle<C> {
+    fn clone(&self) -> FaultHandle<C> {
+        FaultHandle {
+            faults: self.faults.clone(),
+        }
+    }
+}
+
+impl<M> ManageConnection for FaultInjectingManager<M>
+where
+    M: ManageConnection,
+    M::Error: From<InjectedFault>,
+{
+    type Connection = M::Connection;
+    type Error = M::Error;
+
+    fn connect(&self) -> Result<M::Connection, M::Error> {
+        self.faults.delay();
+        {
+            let mut faults = self.faults.lock();
+            if faults.failed_connects > 0 {
+                faults.failed_connects -= 1;
+                return Err(InjectedFault::Connect.into());
+            }
+        }
+
+        let conn = self.inner.connect()?;
+        let failing = self.faults.lock().failing.iter().any(|failing| failing(&conn));
+        if failing {
+            return Err(InjectedFault::Connect.into());
+        }
+        Ok(conn)
+    }
+
+    fn is_valid(&self, conn: &mut M::Connection) -> Result<(), M::Error> {
+        self.faults.delay();
+        let valid = self.faults.lock().valid;
+        match valid {
+            Some(true) => Ok(()),
+            Some(false) => Err(InjectedFault::Validation.into()),
+            None => self.inner.is_valid(conn),
+        }
+    }
+
+    fn has_broken(&self, conn: &mut M::Connection) -> bool {
+        let broken = self.faults.lock().broken;
+        broken.unwrap_or_else(|| self.inner.has_broken(conn))
+    }
+}
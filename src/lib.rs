@@ -36,30 +36,79 @@
 //! }
 //! ```
 
-pub extern crate mongodb;
+#[cfg(feature = "bb8")]
+pub extern crate bb8;
+#[cfg(feature = "deadpool")]
+pub extern crate deadpool;
+#[cfg(all(feature = "mongodb-2", not(feature = "mongodb-3")))]
+pub extern crate mongodb2 as mongodb;
+#[cfg(feature = "mongodb-3")]
+pub extern crate mongodb3 as mongodb;
+#[cfg(not(any(feature = "mongodb-2", feature = "mongodb-3")))]
+compile_error!("either the `mongodb-2` or the `mongodb-3` feature must be enabled");
 pub extern crate r2d2;
 extern crate rand;
 extern crate urlencoding;
 
+#[cfg(any(feature = "bb8", feature = "deadpool"))]
+mod async_manager;
+mod breaker;
 pub mod connstring;
-
-use mongodb::Client;
-use mongodb::Database;
-use mongodb::options::{auth::Credential, ClientOptions, StreamAddress, Tls, TlsOptions};
-use mongodb::error::{Error, ErrorKind::ArgumentError};
+mod driver;
+mod env;
+pub mod error;
+pub mod event;
+#[cfg(feature = "test-util")]
+mod fault;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod pool;
+mod reconfigure;
+mod retry;
+mod tenant;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod trace;
+#[cfg(feature = "serde")]
+mod serialization;
+mod uri_options;
+mod watch;
+
+#[cfg(any(feature = "bb8", feature = "deadpool"))]
+pub use crate::async_manager::AsyncMongodbConnectionManager;
+pub use crate::breaker::CircuitBreaker;
+pub use crate::error::Error;
+pub use crate::event::{ConnectionEventHandler, NopConnectionEventHandler};
+#[cfg(feature = "metrics")]
+pub use crate::metrics::PoolMetrics;
+pub use crate::pool::{pool, PoolConfig};
+pub use crate::reconfigure::OptionsHandle;
+pub use crate::retry::{RetryPolicy, RetryableError};
+pub use crate::tenant::{TenantConnection, TenantPools};
+pub use crate::watch::{ConfigWatcher, WatchGuard};
+
+use mongodb::options::{DatabaseOptions, ReadConcern, ReadPreference, SelectionCriteria, WriteConcern};
 
 use r2d2::ManageConnection;
 
 use rand::seq::SliceRandom;
 use rand::thread_rng;
 
-use std::fmt;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::connstring::parse;
+use crate::breaker::{Attempt, Breaker};
+use crate::connstring::{parse, URI_SCHEME};
+use crate::driver::{Client, Database, TopologyWatch};
+use crate::error::{Component, ParseError};
 
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 pub struct Host {
     /// Address of the MongoDB server
     ///
@@ -81,33 +130,41 @@ impl Default for Host {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Auth {
     /// Username for authentication
     pub username: String,
     /// Password for authentication
+    ///
+    /// Never serialized.
+    #[cfg_attr(feature = "serde", serde(skip_serializing, default))]
     pub password: String,
 }
 
 /// Whether or not to verify that the server's certificate is trusted
-#[derive(Copy, Clone, PartialEq)]
+///
+/// Serialized as a boolean.
+#[derive(Copy, Clone, PartialEq, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "bool", into = "bool")
+)]
 pub enum VerifyPeer {
+    #[default]
     Yes,
     No,
 }
 
-impl Default for VerifyPeer {
-    fn default() -> Self {
-        VerifyPeer::Yes
-    }
-}
-
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SSLCert {
     pub certificate_file: String,
     pub key_file: String,
 }
 
 #[derive(Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 pub struct SSLConfig {
     pub ca_file: Option<String>,
     pub cert: Option<SSLCert>,
@@ -117,7 +174,15 @@ pub struct SSLConfig {
 /// Options with which the connections to MongoDB will be created
 ///
 /// To authenticate the connection you have to provide both a `username` and `password`.
+///
+/// With the `serde` feature, deserializes from either a connection string or a table of
+/// these fields, with `selection_criteria` given as `read_preference`.
 #[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "serialization::ConnectionOptionsRepr")
+)]
 pub struct ConnectionOptions {
     /// List of hosts
     ///
@@ -130,11 +195,74 @@ pub struct ConnectionOptions {
     /// Authentication options
     ///
     /// Default: `None`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub auth: Option<Auth>,
     /// SSL options
     ///
     /// Default: `None`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     pub ssl: Option<SSLConfig>,
+    /// Default read concern for the pooled database handles
+    ///
+    /// Default: `None`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub read_concern: Option<ReadConcern>,
+    /// Default write concern for the pooled database handles
+    ///
+    /// Default: `None`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub write_concern: Option<WriteConcern>,
+    /// Default read preference (or other selection criteria) for the pooled database handles
+    ///
+    /// Default: `None`
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "read_preference",
+            serialize_with = "serialization::serialize_selection_criteria",
+            skip_serializing_if = "serialization::is_unset_read_preference"
+        )
+    )]
+    pub selection_criteria: Option<SelectionCriteria>,
+    /// How failed connects are retried
+    ///
+    /// Default: `None`, connects are not retried
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub retry: Option<RetryPolicy>,
+    /// Whether `connect` reaches the server and authenticates before returning
+    ///
+    /// The driver otherwise connects on first use, so a pool would hand out clients for
+    /// unreachable servers or wrong credentials.
+    ///
+    /// Default: `false`
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "std::ops::Not::not"))]
+    pub handshake: bool,
+    /// Timeout for opening a socket and for selecting a server, set by `connectTimeoutMS`
+    ///
+    /// Default: `None`, the driver's defaults
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "connect_timeout_ms",
+            with = "serialization::millis_opt",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub connect_timeout: Option<Duration>,
+    /// How often the driver checks the server, set by `heartbeatFrequencyMS`
+    ///
+    /// A new primary or election retires connections once a check notices it.
+    ///
+    /// Default: `None`, the driver's default of 10 seconds
+    #[cfg_attr(
+        feature = "serde",
+        serde(
+            rename = "heartbeat_frequency_ms",
+            with = "serialization::millis_opt",
+            skip_serializing_if = "Option::is_none"
+        )
+    )]
+    pub heartbeat_frequency: Option<Duration>,
 }
 
 impl Default for ConnectionOptions {
@@ -144,6 +272,13 @@ impl Default for ConnectionOptions {
             db: "admin".to_string(),
             auth: None,
             ssl: None,
+            read_concern: None,
+            write_concern: None,
+            selection_criteria: None,
+            retry: None,
+            handshake: false,
+            connect_timeout: None,
+            heartbeat_frequency: None,
         }
     }
 }
@@ -152,6 +287,79 @@ impl ConnectionOptions {
     pub fn builder() -> ConnectionOptionsBuilder {
         ConnectionOptionsBuilder(ConnectionOptions::default())
     }
+
+    /// Creates options from a MongoDB connection string.
+    pub fn from_uri(uri: &str) -> Result<ConnectionOptions, Error> {
+        let cs = parse(uri)?;
+        let mut options_builder = ConnectionOptions::builder();
+
+        if let Some(db) = cs.database {
+            options_builder.with_db(&db);
+        }
+
+        if let (Some(user), Some(password)) = (cs.user, cs.password) {
+            // Credentials sit right after the scheme, as `user:password@`
+            let user_position = URI_SCHEME.len();
+            let password_position = user_position + user.len() + 1;
+            options_builder.with_auth(
+                &decode_credential(uri, &user, user_position)?,
+                &decode_credential(uri, &password, password_position)?,
+            );
+        }
+
+        for h in cs.hosts {
+            options_builder.with_host(&h.host_name, h.port);
+        }
+
+        if let Some(timeout) = cs.options.as_ref().and_then(|options| options.get("connectTimeoutMS")) {
+            let millis = timeout
+                .parse::<u64>()
+                .map_err(|_| Error::Config(format!("connectTimeoutMS={} is not a valid number.", timeout)))?;
+            // Zero means no timeout
+            if millis > 0 {
+                options_builder.with_connect_timeout(Duration::from_millis(millis));
+            }
+        }
+
+        if let Some(frequency) = cs.options.as_ref().and_then(|options| options.get("heartbeatFrequencyMS")) {
+            let millis = frequency
+                .parse::<u64>()
+                .map_err(|_| Error::Config(format!("heartbeatFrequencyMS={} is not a valid number.", frequency)))?;
+            options_builder.with_heartbeat_frequency(Duration::from_millis(millis));
+        }
+
+        #[cfg(feature = "ssl")]
+        {
+            if let Some(options) = cs.options {
+                // `ssl` and other aliases are stored under `tls`
+                let ssl_enabled = match options.get("tls") {
+                    Some(tls) if tls.eq_ignore_ascii_case("true") => true,
+                    Some(tls) if tls.eq_ignore_ascii_case("false") => false,
+                    None => false,
+                    Some(_) => {
+                        return Err(Error::Config("Invalid TLS option.".to_string()));
+                    }
+                };
+
+                if ssl_enabled {
+                    options_builder.with_unauthenticated_ssl(None, VerifyPeer::No);
+                }
+            }
+        }
+
+        Ok(options_builder.build())
+    }
+
+    /// Options applied to every `Database` handed out by the pool.
+    ///
+    /// Collections obtained from those handles inherit the same defaults.
+    pub fn database_options(&self) -> DatabaseOptions {
+        let mut options = DatabaseOptions::default();
+        options.read_concern = self.read_concern.clone();
+        options.write_concern = self.write_concern.clone();
+        options.selection_criteria = self.selection_criteria.clone();
+        options
+    }
 }
 
 /// Builder for `ConnectionOptions`
@@ -210,6 +418,49 @@ impl ConnectionOptionsBuilder {
         self
     }
 
+    pub fn with_read_concern(&mut self, read_concern: ReadConcern) -> &mut ConnectionOptionsBuilder {
+        self.0.read_concern = Some(read_concern);
+        self
+    }
+
+    pub fn with_write_concern(&mut self, write_concern: WriteConcern) -> &mut ConnectionOptionsBuilder {
+        self.0.write_concern = Some(write_concern);
+        self
+    }
+
+    pub fn with_read_preference(&mut self, read_preference: ReadPreference) -> &mut ConnectionOptionsBuilder {
+        self.0.selection_criteria = Some(SelectionCriteria::ReadPreference(read_preference));
+        self
+    }
+
+    pub fn with_selection_criteria(
+        &mut self,
+        selection_criteria: SelectionCriteria,
+    ) -> &mut ConnectionOptionsBuilder {
+        self.0.selection_criteria = Some(selection_criteria);
+        self
+    }
+
+    pub fn with_retry(&mut self, retry: RetryPolicy) -> &mut ConnectionOptionsBuilder {
+        self.0.retry = Some(retry);
+        self
+    }
+
+    pub fn with_handshake(&mut self, handshake: bool) -> &mut ConnectionOptionsBuilder {
+        self.0.handshake = handshake;
+        self
+    }
+
+    pub fn with_connect_timeout(&mut self, timeout: Duration) -> &mut ConnectionOptionsBuilder {
+        self.0.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_heartbeat_frequency(&mut self, frequency: Duration) -> &mut ConnectionOptionsBuilder {
+        self.0.heartbeat_frequency = Some(frequency);
+        self
+    }
+
     pub fn build(&self) -> ConnectionOptions {
         self.0.clone()
     }
@@ -217,59 +468,115 @@ impl ConnectionOptionsBuilder {
 
 /// Struct for managing a pool of MongoDB connections
 pub struct MongodbConnectionManager {
-    options: ConnectionOptions,
+    options: OptionsHandle,
+    event_handler: Box<dyn ConnectionEventHandler>,
+    breaker: Option<Breaker>,
+    pool_timeout: Option<Duration>,
+    validates: bool,
 }
 
 impl MongodbConnectionManager {
     pub fn new(options: ConnectionOptions) -> MongodbConnectionManager {
-        MongodbConnectionManager { options }
+        MongodbConnectionManager {
+            options: OptionsHandle::new(options),
+            event_handler: Box::new(NopConnectionEventHandler),
+            breaker: None,
+            pool_timeout: None,
+            validates: true,
+        }
     }
 
     pub fn new_with_uri(uri: &str) -> Result<MongodbConnectionManager, Error> {
-        let cs = parse(uri)?;
-        let mut options_builder = ConnectionOptions::builder();
+        let options = ConnectionOptions::from_uri(uri)?;
+        Ok(MongodbConnectionManager::new(options))
+    }
 
-        if let Some(db) = cs.database {
-            options_builder.with_db(&db);
-        }
+    /// Sets the handler notified of connection lifecycle events.
+    ///
+    /// Default: `NopConnectionEventHandler`
+    pub fn with_event_handler(mut self, handler: Box<dyn ConnectionEventHandler>) -> MongodbConnectionManager {
+        self.event_handler = handler;
+        self
+    }
 
-        if let (Some(user), Some(password)) = (cs.user, cs.password) {
-            options_builder.with_auth(
-                &urlencoding::decode(&user).map_err(map_error)?,
-                &urlencoding::decode(&password).map_err(map_error)?,
-            );
-        }
+    /// Fails fast on hosts that keep failing to connect or validate.
+    ///
+    /// Default: no circuit breaker
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> MongodbConnectionManager {
+        self.breaker = Some(Breaker::new(breaker));
+        self
+    }
 
-        for h in cs.hosts {
-            options_builder.with_host(&h.host_name, h.port);
-        }
+    /// Bounds each `connect`, retries included, by the pool's connection timeout, past
+    /// which r2d2 stops waiting for it. Set by `PoolConfig::build`.
+    ///
+    /// Default: `None`, only `RetryPolicy::max_elapsed` applies
+    pub fn with_pool_timeout(mut self, timeout: Duration) -> MongodbConnectionManager {
+        self.pool_timeout = Some(timeout);
+        self
+    }
 
-        #[cfg(feature = "ssl")]
-        {
-            if let Some(options) = cs.options {
-                let ssl_enabled = match options.get("ssl") {
-                    Some(ssl) if ssl == "true" => true,
-                    Some(ssl) if ssl == "false" => false,
-                    _ => {
-                        Err(Error::ArgumentError("Invalid SSL option.".to_string()))?;
-                        false
-                    }
-                };
+    /// Whether `is_valid` asks the server that a connection still works. Retired
+    /// connections fail it either way. Set by `PoolConfig::build` from `test_on_check_out`.
+    ///
+    /// Default: `true`
+    pub fn with_validation(mut self, validate: bool) -> MongodbConnectionManager {
+        self.validates = validate;
+        self
+    }
 
-                if ssl_enabled {
-                    options_builder.with_unauthenticated_ssl(None, VerifyPeer::No);
-                }
-            }
-        }
+    /// The options new connections are created with.
+    pub fn options(&self) -> Arc<ConnectionOptions> {
+        self.options.current()
+    }
+
+    /// A handle to replace the options while the manager is in use.
+    pub fn options_handle(&self) -> OptionsHandle {
+        self.options.clone()
+    }
 
-        let options = options_builder.build();
-        Ok(MongodbConnectionManager { options })
+    /// Creates a manager and an r2d2 pool builder from a connection string, with the
+    /// builder sized by the URI's pool options (see `PoolConfig::from_uri`).
+    pub fn new_with_uri_and_builder(
+        uri: &str,
+    ) -> Result<(MongodbConnectionManager, r2d2::Builder<MongodbConnectionManager>), Error> {
+        let config = PoolConfig::from_uri(uri)?;
+        let manager = MongodbConnectionManager::new_with_uri(uri)?
+            .with_pool_timeout(config.connection_timeout)
+            .with_validation(config.test_on_check_out);
+        Ok((manager, config.builder()))
     }
 }
 
 pub struct MongoConnection {
     client: Client,
     db: Database,
+    host: Host,
+    created_at: Instant,
+    settings: u64,
+    topology: Arc<TopologyWatch>,
+}
+
+impl MongoConnection {
+    /// The host this connection was created for.
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// When this connection was created.
+    pub fn created_at(&self) -> Instant {
+        self.created_at
+    }
+
+    /// A handle on database `name` through this connection, with the same read and
+    /// write concerns and read preference as the configured database.
+    pub fn database(&self, name: &str) -> Database {
+        let mut options = DatabaseOptions::default();
+        options.read_concern = self.db.read_concern().cloned();
+        options.write_concern = self.db.write_concern().cloned();
+        options.selection_criteria = self.db.selection_criteria().cloned();
+        self.client.database_with_options(name, options)
+    }
 }
 
 impl Deref for MongoConnection {
@@ -285,79 +592,202 @@ impl ManageConnection for MongodbConnectionManager {
     type Error = Error;
 
     fn connect(&self) -> Result<Self::Connection, Error> {
-        let host = self
-            .options
-            .hosts
-            .as_slice()
-            .choose(&mut thread_rng())
-            .ok_or::<Error>(ArgumentError { message: "No host provided".to_string() }.into())?;
-
-        let mut client_options = self
-            .options
-            .ssl
-            .as_ref()
-            .map(|ssl| {
-                let verify_peer = ssl.verify_peer == VerifyPeer::Yes;
-                let ca_file_str = ssl.ca_file.clone();
-
-                match ssl.cert.clone() {
-                    Some(cert) => ClientOptions::builder()
-                        .tls(Tls::Enabled(
-                            TlsOptions::builder()
-                                .ca_file_path(ca_file_str)
-                                .cert_key_file_path(cert.key_file)
-                                .allow_invalid_certificates(!verify_peer)
-                                .build(),
-                        )),
-                    None => ClientOptions::builder()
-                        .tls(Tls::Enabled(
-                            TlsOptions::builder()
-                                .ca_file_path(ca_file_str)
-                                .allow_invalid_certificates(!verify_peer)
-                                .build(),
-                        )),
+        let started = Instant::now();
+        // A single snapshot, so that the attempts of one connect never mix options.
+        let options = self.options();
+        let budget = match (options.retry.as_ref(), self.pool_timeout) {
+            (Some(retry), Some(timeout)) => Some(retry.max_elapsed.min(timeout)),
+            (Some(retry), None) => Some(retry.max_elapsed),
+            (None, timeout) => timeout,
+        };
+        let mut attempt = 1;
+        let mut previous = None;
+        loop {
+            let host = self.choose_host(&options, previous)?;
+            let remaining = budget.map(|budget| budget.saturating_sub(started.elapsed()));
+            let result = self.connect_once(&options, host, remaining);
+            let e = match result {
+                Ok(conn) => return Ok(conn),
+                Err(e) => e,
+            };
+            let retry = match options.retry {
+                Some(ref retry) if attempt < retry.attempts && retry.is_retryable(&e) => retry,
+                _ => return Err(e),
+            };
+            let backoff = retry.backoff(attempt);
+            if budget.is_some_and(|budget| started.elapsed() + backoff >= budget) {
+                return Err(e);
+            }
+            thread::sleep(backoff);
+            attempt += 1;
+            previous = Some(host);
+        }
+    }
+
+    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Error> {
+        if self.is_retired(conn) {
+            return Err(Error::Retired(conn.host.clone()));
+        }
+        if !self.validates {
+            return Ok(());
+        }
+        self.validate(&conn.client, &conn.host)
+    }
+
+    /// Retires connections whose host left the host list, whose client settings changed
+    /// (see `OptionsHandle`), or whose server reported a new primary or election, or
+    /// became unreachable, since the connection was created.
+    ///
+    /// Topology changes come from the driver's background monitoring, so this does no
+    /// I/O. Age is left to r2d2's `max_lifetime`.
+    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
+        let broken = self.is_retired(conn) || conn.topology.changed();
+        if broken {
+            self.event_handler.on_discard(&conn.host);
+        }
+        broken
+    }
+}
+
+impl MongodbConnectionManager {
+    // Gives up on the handshake after `remaining`, the time left of the connect's budget.
+    fn connect_once(
+        &self,
+        options: &ConnectionOptions,
+        host: &Host,
+        remaining: Option<Duration>,
+    ) -> Result<MongoConnection, Error> {
+        let attempt = match self.breaker {
+            Some(ref breaker) => breaker.acquire(host),
+            None => Attempt::Allowed,
+        };
+        if attempt == Attempt::Rejected {
+            let e = Error::CircuitOpen(host.clone());
+            self.event_handler.on_connect_failure(host, &e);
+            return Err(e);
+        }
+
+        let conn = self.connect_to(options, host, remaining);
+        match (conn, self.breaker.as_ref()) {
+            (Err(e), Some(breaker)) => {
+                breaker.record_failure(host);
+                Err(e)
+            }
+            // Without a handshake the probe has to reach the server to count.
+            (Ok(conn), Some(breaker)) if attempt == Attempt::Probe => {
+                if options.handshake {
+                    breaker.record_success(host);
+                    Ok(conn)
+                } else {
+                    self.validate(&conn.client, host).map(|()| conn)
                 }
-                .hosts(vec!(StreamAddress {
-                    hostname: host.hostname.clone(),
-                    port: Some(host.port),
-                }))
-                .build()
-            })
-            .unwrap_or(ClientOptions::builder()
-                .hosts(vec!(StreamAddress {
-                    hostname: host.hostname.clone(),
-                    port: Some(host.port),
-                }))
-                .build()
-            );
+            }
+            (conn, _) => conn,
+        }
+    }
 
-        if let Some(ref auth) = self.options.auth {
-            client_options.credential = Some(
-                Credential::builder()
-                    .username(auth.username.clone())
-                    .password(auth.password.clone())
-                    .build()
-            );
+    // Picks a random host, skipping hosts whose circuit is open and the host that just
+    // failed while others are available.
+    fn choose_host<'a>(&self, options: &'a ConnectionOptions, previous: Option<&Host>) -> Result<&'a Host, Error> {
+        let hosts = &options.hosts;
+        let mut available: Vec<&Host> = hosts
+            .iter()
+            .filter(|host| self.breaker.as_ref().is_none_or(|breaker| breaker.is_available(host)))
+            .collect();
+        if available.len() > 1 {
+            available.retain(|host| !previous.is_some_and(|previous| std::ptr::eq(*host, previous)));
         }
+        available
+            .choose(&mut thread_rng())
+            .copied()
+            .or_else(|| hosts.choose(&mut thread_rng()))
+            .ok_or_else(|| Error::Config("No host provided".to_string()))
+    }
 
-        let client = Client::with_options(client_options)?;
-        let db = client.database(&self.options.db);
+    fn connect_to(
+        &self,
+        options: &ConnectionOptions,
+        host: &Host,
+        remaining: Option<Duration>,
+    ) -> Result<MongoConnection, Error> {
+        self.event_handler.on_connect_start(host);
+        let started = Instant::now();
+        let span = trace::connect(options, host);
+        let result = span.in_scope(|| driver::connect(options, host, remaining));
+        trace::outcome(&span, &result);
+        match result {
+            Ok((client, db, topology)) => {
+                self.event_handler.on_connect_success(host, started.elapsed());
+                Ok(MongoConnection {
+                    client,
+                    db,
+                    host: host.clone(),
+                    created_at: started,
+                    settings: fingerprint(options),
+                    topology,
+                })
+            }
+            Err(e) => {
+                self.event_handler.on_connect_failure(host, &e);
+                Err(e)
+            }
+        }
+    }
 
-        Ok(MongoConnection {
-            client, db,
-        })
+    // Shared with the async manager, which validates a cloned client off the runtime.
+    pub(crate) fn validate(&self, client: &Client, host: &Host) -> Result<(), Error> {
+        self.check(host, || driver::validate(client))
     }
 
-    fn is_valid(&self, conn: &mut Self::Connection) -> Result<(), Error> {
-        conn.client.list_database_names(None)?;
-        Ok(())
+    /// Whether `conn` was created for a host or with settings no longer configured.
+    pub(crate) fn is_retired(&self, conn: &MongoConnection) -> bool {
+        let options = self.options();
+        !options.hosts.contains(&conn.host) || conn.settings != fingerprint(&options)
     }
 
-    fn has_broken(&self, conn: &mut Self::Connection) -> bool {
-        self.is_valid(conn).is_err()
+    // Runs a health check on `host`, reporting it to the circuit breaker, traces and events.
+    fn check<T, F: FnOnce() -> Result<T, Error>>(&self, host: &Host, check: F) -> Result<T, Error> {
+        if self.breaker.as_ref().is_some_and(|breaker| breaker.is_open(host)) {
+            return Err(Error::CircuitOpen(host.clone()));
+        }
+
+        let span = trace::validate(host);
+        let result = span.in_scope(check);
+        trace::outcome(&span, &result);
+        if let Some(ref breaker) = self.breaker {
+            match result {
+                Ok(_) => breaker.record_success(host),
+                Err(_) => breaker.record_failure(host),
+            }
+        }
+        result.inspect_err(|e| self.event_handler.on_validation_failure(host, e))
     }
 }
 
-fn map_error<T: fmt::Debug>(e: T) -> Error {
-    ArgumentError { message: format!("{:?}", e) }.into()
+// Identifies the settings a client is created with, other than its host, without keeping
+// the password around. Read and write concerns and the read preference are left out.
+fn fingerprint(options: &ConnectionOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    options.db.hash(&mut hasher);
+    options.connect_timeout.hash(&mut hasher);
+    options.heartbeat_frequency.hash(&mut hasher);
+    options.auth.as_ref().map(|auth| (&auth.username, &auth.password)).hash(&mut hasher);
+    let ssl = options.ssl.as_ref().map(|ssl| {
+        let cert = ssl.cert.as_ref().map(|cert| (&cert.certificate_file, &cert.key_file));
+        (&ssl.ca_file, cert, ssl.verify_peer == VerifyPeer::No)
+    });
+    ssl.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn decode_credential(uri: &str, value: &str, position: usize) -> Result<String, Error> {
+    urlencoding::decode(value).map_err(|e| {
+        ParseError::new(
+            Component::Credentials,
+            position..position + value.len(),
+            format!("Credentials must be valid percent-encoded UTF-8: {}", e),
+        )
+        .with_input(connstring::redact(uri))
+        .into()
+    })
 }
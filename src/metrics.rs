@@ -0,0 +1,195 @@
+//! Pool metrics in the OpenMetrics text format.
+//!
+//! `PoolMetrics` is both a `ConnectionEventHandler` for the manager and an r2d2
+//! `HandleEvent` for the pool; clones share the same counters:
+//!
+//! ```rust,no_run
+//! # extern crate r2d2;
+//! # extern crate r2d2_mongodb;
+//! # use r2d2_mongodb::{MongodbConnectionManager, PoolMetrics};
+//! # fn main() -> Result<(), r2d2_mongodb::Error> {
+//! let metrics = PoolMetrics::new();
+//! let manager = MongodbConnectionManager::new_with_uri("mongodb://localhost:27017/mydb")?
+//!     .with_event_handler(Box::new(metrics.clone()));
+//! let pool = r2d2::Pool::builder()
+//!     .event_handler(Box::new(metrics.clone()))
+//!     .build(manager)
+//!     .map_err(r2d2_mongodb::Error::Pool)?;
+//!
+//! let text = metrics.render(&pool.state());
+//! # Ok(())
+//! # }
+//! ```
+use r2d2::event::{CheckoutEvent, HandleEvent, TimeoutEvent};
+use r2d2::State;
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::event::ConnectionEventHandler;
+use crate::Host;
+
+const PREFIX: &str = "r2d2_mongodb";
+
+// Upper bounds of the latency histogram buckets, in seconds.
+const BUCKETS: &[f64] = &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Shared collector of pool and connection metrics
+#[derive(Clone, Debug, Default)]
+pub struct PoolMetrics {
+    inner: Arc<Mutex<Metrics>>,
+}
+
+#[derive(Debug, Default)]
+struct Metrics {
+    connect_duration: BTreeMap<String, Histogram>,
+    connect_failures: BTreeMap<(String, &'static str), u64>,
+    validation_failures: BTreeMap<String, u64>,
+    discarded: BTreeMap<String, u64>,
+    checkout_wait: Histogram,
+    checkout_timeouts: u64,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Histogram {
+        Histogram {
+            counts: vec![0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (count, &bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+impl PoolMetrics {
+    pub fn new() -> PoolMetrics {
+        PoolMetrics::default()
+    }
+
+    /// Renders all metrics, with live connection counts taken from `state`.
+    pub fn render(&self, state: &State) -> String {
+        let metrics = self.lock();
+        let mut out = String::new();
+        // Writing to a `String` cannot fail.
+        metrics.write(&mut out, state).unwrap();
+        out
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Metrics> {
+        // Counters stay usable if a panic happened while they were updated.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Metrics {
+    fn write(&self, out: &mut String, state: &State) -> fmt::Result {
+        header(out, "connect_duration_seconds", "histogram", "Time taken to create a connection.")?;
+        for (host, histogram) in &self.connect_duration {
+            write_histogram(out, "connect_duration_seconds", &label("host", host), histogram)?;
+        }
+
+        header(out, "connect_failures", "counter", "Failed connection attempts.")?;
+        for ((host, kind), count) in &self.connect_failures {
+            let labels = format!("{},{}", label("host", host), label("kind", kind));
+            writeln!(out, "{}_connect_failures_total{{{}}} {}", PREFIX, labels, count)?;
+        }
+
+        header(out, "validation_failures", "counter", "Connections that failed their health check.")?;
+        for (host, count) in &self.validation_failures {
+            writeln!(out, "{}_validation_failures_total{{{}}} {}", PREFIX, label("host", host), count)?;
+        }
+
+        header(out, "discarded_connections", "counter", "Broken connections dropped by the pool.")?;
+        for (host, count) in &self.discarded {
+            writeln!(out, "{}_discarded_connections_total{{{}}} {}", PREFIX, label("host", host), count)?;
+        }
+
+        header(out, "checkout_wait_seconds", "histogram", "Time spent waiting for a connection.")?;
+        write_histogram(out, "checkout_wait_seconds", "", &self.checkout_wait)?;
+
+        header(out, "checkout_timeouts", "counter", "Checkouts that timed out.")?;
+        writeln!(out, "{}_checkout_timeouts_total {}", PREFIX, self.checkout_timeouts)?;
+
+        header(out, "connections", "gauge", "Connections managed by the pool.")?;
+        let in_use = state.connections - state.idle_connections;
+        writeln!(out, "{}_connections{{state=\"idle\"}} {}", PREFIX, state.idle_connections)?;
+        writeln!(out, "{}_connections{{state=\"in_use\"}} {}", PREFIX, in_use)?;
+
+        writeln!(out, "# EOF")
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) -> fmt::Result {
+    writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind)?;
+    writeln!(out, "# HELP {}_{} {}", PREFIX, name, help)
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) -> fmt::Result {
+    let sep = if labels.is_empty() { "" } else { "," };
+    for (count, bound) in histogram.counts.iter().zip(BUCKETS) {
+        writeln!(out, "{}_{}_bucket{{{}{}le=\"{}\"}} {}", PREFIX, name, labels, sep, bound, count)?;
+    }
+    writeln!(out, "{}_{}_bucket{{{}{}le=\"+Inf\"}} {}", PREFIX, name, labels, sep, histogram.count)?;
+    let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+    writeln!(out, "{}_{}_sum{} {}", PREFIX, name, labels, histogram.sum)?;
+    writeln!(out, "{}_{}_count{} {}", PREFIX, name, labels, histogram.count)
+}
+
+fn label(name: &str, value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("{}=\"{}\"", name, escaped)
+}
+
+fn host_label(host: &Host) -> String {
+    format!("{}:{}", host.hostname, host.port)
+}
+
+impl ConnectionEventHandler for PoolMetrics {
+    fn on_connect_success(&self, host: &Host, duration: Duration) {
+        self.lock().connect_duration.entry(host_label(host)).or_default().observe(duration);
+    }
+
+    fn on_connect_failure(&self, host: &Host, error: &Error) {
+        *self.lock().connect_failures.entry((host_label(host), error.kind())).or_insert(0) += 1;
+    }
+
+    fn on_validation_failure(&self, host: &Host, _error: &Error) {
+        *self.lock().validation_failures.entry(host_label(host)).or_insert(0) += 1;
+    }
+
+    fn on_discard(&self, host: &Host) {
+        *self.lock().discarded.entry(host_label(host)).or_insert(0) += 1;
+    }
+}
+
+impl HandleEvent for PoolMetrics {
+    fn handle_checkout(&self, event: CheckoutEvent) {
+        self.lock().checkout_wait.observe(event.duration());
+    }
+
+    fn handle_timeout(&self, _event: TimeoutEvent) {
+        self.lock().checkout_timeouts += 1;
+    }
+}
@@ -0,0 +1,153 @@
+//! r2d2 pool settings with MongoDB defaults.
+use r2d2::{Builder, Pool};
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::connstring::{self, parse};
+use crate::error::Error;
+use crate::{ConnectionOptions, MongodbConnectionManager};
+
+/// Settings for the r2d2 pool of MongoDB connections
+///
+/// Every pooled connection is a driver client that monitors its servers and reconnects
+/// on its own, so by default connections are only checked on check out for having been
+/// retired by an options change, without asking the server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PoolConfig {
+    /// Maximum number of connections, set by the `maxPoolSize` URI option
+    ///
+    /// Without `maxPoolSize`, raised to `minPoolSize` if that is larger.
+    ///
+    /// Default: `10`
+    pub max_size: u32,
+    /// Number of idle connections to keep, set by the `minPoolSize` URI option
+    ///
+    /// Default: `Some(1)`
+    pub min_idle: Option<u32>,
+    /// How long to wait for a connection, set by the `waitQueueTimeoutMS` URI option
+    ///
+    /// Default: `30s`, matching the driver's server selection timeout
+    pub connection_timeout: Duration,
+    /// How long a connection may stay idle, set by the `maxIdleTimeMS` URI option
+    ///
+    /// Default: `Some(10min)`
+    pub idle_timeout: Option<Duration>,
+    /// How long a connection may live
+    ///
+    /// Default: `Some(30min)`
+    pub max_lifetime: Option<Duration>,
+    /// Whether to ask the server that connections work before handing them out
+    ///
+    /// Retired connections (see `OptionsHandle`) are never handed out either way.
+    ///
+    /// Default: `false`
+    pub test_on_check_out: bool,
+}
+
+impl Default for PoolConfig {
+    fn default() -> PoolConfig {
+        PoolConfig {
+            max_size: 10,
+            min_idle: Some(1),
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+            max_lifetime: Some(Duration::from_secs(30 * 60)),
+            test_on_check_out: false,
+        }
+    }
+}
+
+impl PoolConfig {
+    /// Reads the pool settings of a MongoDB connection string, using defaults for the rest.
+    pub fn from_uri(uri: &str) -> Result<PoolConfig, Error> {
+        let cs = parse(uri)?;
+        match cs.options {
+            Some(ref options) => PoolConfig::from_uri_options(options),
+            None => Ok(PoolConfig::default()),
+        }
+    }
+
+    /// Applies `maxPoolSize`, `minPoolSize`, `maxIdleTimeMS` and `waitQueueTimeoutMS` to the
+    /// defaults.
+    pub fn from_uri_options(options: &connstring::ConnectionOptions) -> Result<PoolConfig, Error> {
+        let mut config = PoolConfig::default();
+
+        let max_size = number::<u32>(options, "maxPoolSize")?;
+        if let Some(max_size) = max_size {
+            if max_size == 0 {
+                return Err(Error::Config(String::from("maxPoolSize=0 (no limit) is not supported.")));
+            }
+            config.max_size = max_size;
+        }
+        if let Some(min_idle) = number::<u32>(options, "minPoolSize")? {
+            match max_size {
+                Some(max_size) if min_idle > max_size => {
+                    return Err(Error::Config(format!(
+                        "minPoolSize={} is larger than maxPoolSize={}.",
+                        min_idle, max_size
+                    )));
+                }
+                // Without maxPoolSize, the default size grows to hold the idle connections
+                Some(_) => {}
+                None => config.max_size = config.max_size.max(min_idle),
+            }
+            config.min_idle = Some(min_idle);
+        }
+        if let Some(idle_ms) = number::<u64>(options, "maxIdleTimeMS")? {
+            // Zero means connections may stay idle forever
+            config.idle_timeout = Some(Duration::from_millis(idle_ms)).filter(|d| !d.is_zero());
+        }
+        if let Some(wait_ms) = number::<u64>(options, "waitQueueTimeoutMS")? {
+            if wait_ms == 0 {
+                return Err(Error::Config(String::from(
+                    "waitQueueTimeoutMS=0 (wait forever) is not supported.",
+                )));
+            }
+            config.connection_timeout = Duration::from_millis(wait_ms);
+        }
+
+        Ok(config)
+    }
+
+    /// An r2d2 pool builder with these settings.
+    ///
+    /// r2d2 always tests connections on check out; give the manager
+    /// `with_validation(test_on_check_out)`, as `build` does, to only round-trip to the
+    /// server when `test_on_check_out` is set.
+    pub fn builder(&self) -> Builder<MongodbConnectionManager> {
+        Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+            .max_lifetime(self.max_lifetime)
+            .test_on_check_out(true)
+    }
+
+    /// Builds a pool with these settings, waiting for its initial connections.
+    ///
+    /// Connects, retries included, are bounded by `connection_timeout`, and the manager
+    /// only validates connections with the server if `test_on_check_out` is set.
+    pub fn build(&self, manager: MongodbConnectionManager) -> Result<Pool<MongodbConnectionManager>, Error> {
+        let manager = manager
+            .with_pool_timeout(self.connection_timeout)
+            .with_validation(self.test_on_check_out);
+        self.builder().build(manager).map_err(Error::Pool)
+    }
+}
+
+/// Builds a pool for `options` with the default `PoolConfig`.
+pub fn pool(options: ConnectionOptions) -> Result<Pool<MongodbConnectionManager>, Error> {
+    PoolConfig::default().build(MongodbConnectionManager::new(options))
+}
+
+fn number<T: FromStr>(options: &connstring::ConnectionOptions, key: &str) -> Result<Option<T>, Error> {
+    match options.get(key) {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::Config(format!("{}={} is not a valid number.", key, value))),
+        None => Ok(None),
+    }
+}
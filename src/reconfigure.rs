@@ -0,0 +1,40 @@
+//! Swapping the options of a live manager.
+use std::sync::{Arc, RwLock};
+
+use crate::error::Error;
+use crate::ConnectionOptions;
+
+/// Replaces the `ConnectionOptions` of a running `MongodbConnectionManager`
+///
+/// Connections created after `update` use the new options. Pooled connections to hosts
+/// that were removed, or created with a different database, credentials, TLS settings,
+/// connect timeout or heartbeat frequency, are discarded with `Error::Retired` when they
+/// are next checked in or out. Read and write concerns and the read preference are
+/// not compared, so connections keep the ones they were created with until they are
+/// discarded for another reason. Clones control the same manager.
+#[derive(Clone)]
+pub struct OptionsHandle {
+    options: Arc<RwLock<Arc<ConnectionOptions>>>,
+}
+
+impl OptionsHandle {
+    pub(crate) fn new(options: ConnectionOptions) -> OptionsHandle {
+        OptionsHandle {
+            options: Arc::new(RwLock::new(Arc::new(options))),
+        }
+    }
+
+    /// The options new connections are created with.
+    pub fn current(&self) -> Arc<ConnectionOptions> {
+        self.options.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Replaces the options; fails and keeps the current ones if `options` has no host.
+    pub fn update(&self, options: ConnectionOptions) -> Result<(), Error> {
+        if options.hosts.is_empty() {
+            return Err(Error::Config("No host provided".to_string()));
+        }
+        *self.options.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(options);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! Retrying failed connects.
+use rand::{thread_rng, Rng};
+
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// Errors that a `RetryPolicy` may retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(rename_all = "snake_case"))]
+pub enum RetryableError {
+    /// `Error::Connect`
+    Connect,
+    /// `Error::Auth`
+    Auth,
+    /// `Error::Validation`, raised when a new connection is checked against the server
+    Validation,
+    /// `Error::CircuitOpen`
+    CircuitOpen,
+}
+
+/// How `connect` retries failed attempts
+///
+/// Between attempts, `connect` waits for an exponentially growing, jittered delay and
+/// switches to another host when there is one. No attempt starts once `max_elapsed`, or
+/// the pool's connection timeout if shorter (see `MongodbConnectionManager::with_pool_timeout`),
+/// would be exceeded. With a handshake, each attempt gives up on it once the time is up;
+/// the created client still uses the configured timeouts.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first one
+    ///
+    /// Default: `3`
+    pub attempts: u32,
+    /// Delay before the second attempt, doubled for each further attempt
+    ///
+    /// Default: `100ms`
+    #[cfg_attr(feature = "serde", serde(rename = "initial_backoff_ms", with = "crate::serialization::millis"))]
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts
+    ///
+    /// Default: `5s`
+    #[cfg_attr(feature = "serde", serde(rename = "max_backoff_ms", with = "crate::serialization::millis"))]
+    pub max_backoff: Duration,
+    /// Time after which no further attempt is made
+    ///
+    /// Default: `30s`, r2d2's default connection timeout
+    #[cfg_attr(feature = "serde", serde(rename = "max_elapsed_ms", with = "crate::serialization::millis"))]
+    pub max_elapsed: Duration,
+    /// Errors worth retrying
+    ///
+    /// Default: `[Connect, Validation]`
+    pub retryable: Vec<RetryableError>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            max_elapsed: Duration::from_secs(30),
+            retryable: vec![RetryableError::Connect, RetryableError::Validation],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether `error` may be retried.
+    pub fn is_retryable(&self, error: &Error) -> bool {
+        let kind = match *error {
+            Error::Connect(_) => RetryableError::Connect,
+            Error::Auth(_) => RetryableError::Auth,
+            Error::Validation(_) => RetryableError::Validation,
+            Error::CircuitOpen(_) => RetryableError::CircuitOpen,
+            _ => return false,
+        };
+        self.retryable.contains(&kind)
+    }
+
+    /// Delay after the failed `attempt` (starting at 1), between half and all of the
+    /// exponential backoff.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .checked_mul(1 << attempt.saturating_sub(1).min(31))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        exponential.mul_f64(thread_rng().gen_range(0.5, 1.0))
+    }
+}
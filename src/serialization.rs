@@ -0,0 +1,173 @@
+//! Serde support for the configuration types.
+//!
+//! `ConnectionOptions` deserializes from either a connection string or a table of its
+//! fields. Passwords are never serialized.
+use mongodb::options::{ReadConcern, ReadPreference, SelectionCriteria, WriteConcern};
+
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serializer};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{Auth, ConnectionOptions, Error, Host, RetryPolicy, SSLConfig, VerifyPeer};
+
+pub(crate) enum ConnectionOptionsRepr {
+    Uri(String),
+    Table(Box<ConnectionOptionsTable>),
+}
+
+// Dispatches on the input type by hand: an untagged enum would replace the errors of
+// the table, such as unknown fields, with a generic "did not match any variant".
+impl<'de> Deserialize<'de> for ConnectionOptionsRepr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ConnectionOptionsRepr, D::Error> {
+        deserializer.deserialize_any(ReprVisitor)
+    }
+}
+
+struct ReprVisitor;
+
+impl<'de> Visitor<'de> for ReprVisitor {
+    type Value = ConnectionOptionsRepr;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a connection string or a table of connection options")
+    }
+
+    fn visit_str<E: de::Error>(self, uri: &str) -> Result<ConnectionOptionsRepr, E> {
+        Ok(ConnectionOptionsRepr::Uri(uri.to_string()))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ConnectionOptionsRepr, A::Error> {
+        let table = ConnectionOptionsTable::deserialize(MapAccessDeserializer::new(map))?;
+        Ok(ConnectionOptionsRepr::Table(Box::new(table)))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ConnectionOptionsTable {
+    hosts: Vec<Host>,
+    db: String,
+    auth: Option<Auth>,
+    ssl: Option<SSLConfig>,
+    read_concern: Option<ReadConcern>,
+    write_concern: Option<WriteConcern>,
+    read_preference: Option<ReadPreference>,
+    retry: Option<RetryPolicy>,
+    handshake: bool,
+    #[serde(rename = "connect_timeout_ms", with = "millis_opt")]
+    connect_timeout: Option<Duration>,
+    #[serde(rename = "heartbeat_frequency_ms", with = "millis_opt")]
+    heartbeat_frequency: Option<Duration>,
+}
+
+impl Default for ConnectionOptionsTable {
+    fn default() -> ConnectionOptionsTable {
+        let defaults = ConnectionOptions::default();
+        ConnectionOptionsTable {
+            hosts: defaults.hosts,
+            db: defaults.db,
+            auth: None,
+            ssl: None,
+            read_concern: None,
+            write_concern: None,
+            read_preference: None,
+            retry: None,
+            handshake: defaults.handshake,
+            connect_timeout: None,
+            heartbeat_frequency: None,
+        }
+    }
+}
+
+impl TryFrom<ConnectionOptionsRepr> for ConnectionOptions {
+    type Error = Error;
+
+    fn try_from(repr: ConnectionOptionsRepr) -> Result<ConnectionOptions, Error> {
+        match repr {
+            ConnectionOptionsRepr::Uri(uri) => ConnectionOptions::from_uri(&uri),
+            ConnectionOptionsRepr::Table(table) => Ok(ConnectionOptions {
+                hosts: table.hosts,
+                db: table.db,
+                auth: table.auth,
+                ssl: table.ssl,
+                read_concern: table.read_concern,
+                write_concern: table.write_concern,
+                selection_criteria: table.read_preference.map(SelectionCriteria::ReadPreference),
+                retry: table.retry,
+                handshake: table.handshake,
+                connect_timeout: table.connect_timeout,
+                heartbeat_frequency: table.heartbeat_frequency,
+            }),
+        }
+    }
+}
+
+// Only read preferences can be written out; predicates are dropped.
+pub(crate) fn serialize_selection_criteria<S: Serializer>(
+    criteria: &Option<SelectionCriteria>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match *criteria {
+        Some(SelectionCriteria::ReadPreference(ref read_preference)) => {
+            serializer.serialize_some(read_preference)
+        }
+        _ => serializer.serialize_none(),
+    }
+}
+
+pub(crate) fn is_unset_read_preference(criteria: &Option<SelectionCriteria>) -> bool {
+    !matches!(*criteria, Some(SelectionCriteria::ReadPreference(_)))
+}
+
+// Durations as whole milliseconds.
+pub(crate) mod millis {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+// Optional durations as whole milliseconds.
+pub(crate) mod millis_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use std::time::Duration;
+
+    pub(crate) fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match *duration {
+            Some(ref duration) => super::millis::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<u64>::deserialize(deserializer).map(|millis| millis.map(Duration::from_millis))
+    }
+}
+
+impl From<bool> for VerifyPeer {
+    fn from(verify: bool) -> VerifyPeer {
+        if verify {
+            VerifyPeer::Yes
+        } else {
+            VerifyPeer::No
+        }
+    }
+}
+
+impl From<VerifyPeer> for bool {
+    fn from(verify_peer: VerifyPeer) -> bool {
+        verify_peer == VerifyPeer::Yes
+    }
+}
@@ -0,0 +1,217 @@
+//! Per-tenant databases on a shared cluster.
+use r2d2::{Pool, PooledConnection};
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::driver::Database;
+use crate::error::Error;
+use crate::MongodbConnectionManager;
+
+/// Connections to many databases on the same cluster, drawn from one shared pool
+///
+/// `get` checks a connection out of the pool and hands out a handle on the tenant's
+/// database, through the connection's client and with its read and write concerns. Each
+/// tenant may hold at most its limit of connections at once, so one busy tenant cannot
+/// take the whole pool; callers over the limit wait up to the pool's connection timeout.
+/// Tenants are tracked from their first `get` until they go unused for the idle timeout.
+///
+/// ```rust
+/// # extern crate r2d2_mongodb;
+/// # use r2d2_mongodb::{ConnectionOptions, MongodbConnectionManager, PoolConfig, TenantPools};
+/// let options = ConnectionOptions::builder().with_host("localhost", 27017).build();
+/// let config = PoolConfig {
+///     max_size: 32,
+///     min_idle: Some(0),
+///     ..PoolConfig::default()
+/// };
+/// let pool = config.build(MongodbConnectionManager::new(options)).unwrap();
+/// let tenants = TenantPools::new(pool)
+///     .with_max_connections(4)
+///     .with_tenant_max_connections("big_customer", 16);
+/// ```
+pub struct TenantPools {
+    pool: Pool<MongodbConnectionManager>,
+    max_connections: u32,
+    tenant_max_connections: HashMap<String, u32>,
+    idle_timeout: Option<Duration>,
+    tenants: Mutex<HashMap<String, Entry>>,
+}
+
+struct Entry {
+    tenant: Arc<Tenant>,
+    last_used: Instant,
+}
+
+// Counts the connections a tenant holds.
+struct Tenant {
+    max_connections: u32,
+    in_use: Mutex<u32>,
+    released: Condvar,
+}
+
+/// A pooled connection handed out for a tenant's database
+///
+/// Dereferences to the tenant's `Database`. Dropping it returns the connection to the
+/// shared pool and frees the tenant's slot.
+pub struct TenantConnection {
+    db: Database,
+    _conn: PooledConnection<MongodbConnectionManager>,
+    _permit: Permit,
+}
+
+struct Permit(Arc<Tenant>);
+
+impl TenantPools {
+    pub fn new(pool: Pool<MongodbConnectionManager>) -> TenantPools {
+        TenantPools {
+            max_connections: pool.max_size(),
+            pool,
+            tenant_max_connections: HashMap::new(),
+            idle_timeout: Some(Duration::from_secs(30 * 60)),
+            tenants: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sets how many connections a tenant without its own limit may hold at once.
+    ///
+    /// Default: the pool's `max_size`
+    pub fn with_max_connections(mut self, max_connections: u32) -> TenantPools {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Sets how many connections the tenant using database `db` may hold at once.
+    pub fn with_tenant_max_connections(mut self, db: &str, max_connections: u32) -> TenantPools {
+        self.tenant_max_connections.insert(db.to_string(), max_connections);
+        self
+    }
+
+    /// Sets how long an unused tenant is tracked; `None` keeps tenants until they are
+    /// removed.
+    ///
+    /// Default: `Some(30min)`
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> TenantPools {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// The shared pool.
+    pub fn pool(&self) -> &Pool<MongodbConnectionManager> {
+        &self.pool
+    }
+
+    /// A connection to database `db`, waiting up to the pool's connection timeout for
+    /// the tenant to be under its limit and for the pool to have a connection.
+    ///
+    /// Also forgets idle tenants.
+    pub fn get(&self, db: &str) -> Result<TenantConnection, Error> {
+        check_db(db)?;
+        self.evict_idle();
+        let tenant = self.tenant(db);
+
+        let deadline = Instant::now() + self.pool.connection_timeout();
+        let permit = tenant.acquire(deadline).ok_or_else(|| Error::TenantLimit(db.to_string()))?;
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let conn = self.pool.get_timeout(timeout).map_err(Error::Pool)?;
+        Ok(TenantConnection {
+            db: conn.database(db),
+            _conn: conn,
+            _permit: permit,
+        })
+    }
+
+    /// Stops tracking database `db`, returning whether it was tracked. Connections it
+    /// still holds no longer count against its limit.
+    pub fn remove(&self, db: &str) -> bool {
+        self.lock().remove(db).is_some()
+    }
+
+    /// Forgets tenants without connections that have been unused for longer than the
+    /// idle timeout, returning how many were forgotten.
+    pub fn evict_idle(&self) -> usize {
+        let idle_timeout = match self.idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return 0,
+        };
+        let mut tenants = self.lock();
+        let before = tenants.len();
+        // Tenants are shared with their connections and with callers waiting for one.
+        tenants.retain(|_, entry| Arc::strong_count(&entry.tenant) > 1 || entry.last_used.elapsed() < idle_timeout);
+        before - tenants.len()
+    }
+
+    /// Number of tracked tenants.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Creates the tenant on first use; the lock makes concurrent first calls share it.
+    fn tenant(&self, db: &str) -> Arc<Tenant> {
+        let mut tenants = self.lock();
+        let entry = tenants.entry(db.to_string()).or_insert_with(|| {
+            let max_connections = self.tenant_max_connections.get(db).copied().unwrap_or(self.max_connections);
+            Entry {
+                tenant: Arc::new(Tenant {
+                    max_connections,
+                    in_use: Mutex::new(0),
+                    released: Condvar::new(),
+                }),
+                last_used: Instant::now(),
+            }
+        });
+        entry.last_used = Instant::now();
+        entry.tenant.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        self.tenants.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Tenant {
+    fn acquire(self: Arc<Tenant>, deadline: Instant) -> Option<Permit> {
+        let mut in_use = self.in_use.lock().unwrap_or_else(|e| e.into_inner());
+        while *in_use >= self.max_connections {
+            let timeout = deadline.checked_duration_since(Instant::now())?;
+            in_use = self
+                .released
+                .wait_timeout(in_use, timeout)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        *in_use += 1;
+        drop(in_use);
+        Some(Permit(self))
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.0.in_use.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl Deref for TenantConnection {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+// Tenant names usually come from requests, so they are checked against MongoDB's rules for
+// database names before they are tracked.
+fn check_db(db: &str) -> Result<(), Error> {
+    if db.is_empty() || db.len() > 63 || db.contains(['/', '\\', '.', ' ', '"', '$', '\0']) {
+        return Err(Error::Config(format!("Invalid database name '{}'.", db)));
+    }
+    Ok(())
+}
@@ -0,0 +1,492 @@
+//! In-process MongoDB server for tests.
+//!
+//! `MockServer` speaks just enough of the wire protocol (OP_MSG, and OP_QUERY for legacy
+//! handshakes) for the driver to connect, authenticate with SCRAM-SHA-256 and run the
+//! manager's health check. It answers `hello`/`isMaster`, `ping`, `saslStart`,
+//! `saslContinue`, `listDatabases` and `endSessions`; anything else fails with
+//! `CommandNotFound`. Commands can be scripted to fail with `MockServer::fail_command`.
+//!
+//! `FaultInjectingManager` fails on demand without touching the network, for testing
+//! code built on the pool.
+//!
+//! ```rust,no_run
+//! # extern crate r2d2;
+//! # extern crate r2d2_mongodb;
+//! # use r2d2::ManageConnection;
+//! # use r2d2_mongodb::test_util::MockServer;
+//! # use r2d2_mongodb::MongodbConnectionManager;
+//! let server = MockServer::start().unwrap();
+//! let manager = MongodbConnectionManager::new_with_uri(&server.uri()).unwrap();
+//! let mut conn = manager.connect().unwrap();
+//! assert!(manager.is_valid(&mut conn).is_ok());
+//! ```
+use hmac::{Hmac, Mac};
+
+use mongodb::bson::spec::BinarySubtype;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, Binary, Bson, DateTime, Document};
+
+use rand::{thread_rng, Rng};
+
+use sha2::{Digest, Sha256};
+
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use crate::Host;
+
+pub use crate::fault::{FaultHandle, FaultInjectingManager, InjectedFault};
+
+const OP_REPLY: i32 = 1;
+const OP_QUERY: i32 = 2004;
+const OP_MSG: i32 = 2013;
+
+const SCRAM_ITERATIONS: u32 = 4096;
+
+/// A scripted failure for a command.
+#[derive(Clone, Debug)]
+pub enum Fault {
+    /// Reply with `ok: 0` and the given error code and message
+    CommandError {
+        /// Server error code, e.g. `18` for `AuthenticationFailed`
+        code: i32,
+        /// Error message
+        message: String,
+    },
+    /// Close the connection instead of replying
+    CloseConnection,
+    /// Wait before replying normally
+    Delay(Duration),
+}
+
+/// A minimal MongoDB server listening on a local port
+///
+/// The server stops and closes its connections when dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<State>,
+}
+
+struct State {
+    address: SocketAddr,
+    user: Option<User>,
+    replica_set: Option<String>,
+    election_id: Mutex<ObjectId>,
+    faults: Mutex<Vec<(String, Fault, usize)>>,
+    received: Mutex<Vec<String>>,
+    streams: Mutex<Vec<TcpStream>>,
+    stopped: AtomicBool,
+    next_id: AtomicI32,
+}
+
+struct User {
+    username: String,
+    salt: Vec<u8>,
+    salted_password: Vec<u8>,
+}
+
+// Per-connection SCRAM conversation.
+#[derive(Default)]
+struct Connection {
+    id: i32,
+    authenticated: bool,
+    scram: Option<(String, String)>,
+}
+
+impl MockServer {
+    /// Starts a server that does not require authentication.
+    pub fn start() -> io::Result<MockServer> {
+        MockServer::listen(None, None)
+    }
+
+    /// Starts a server that reports itself as the primary of replica set `name`.
+    pub fn start_replica_set(name: &str) -> io::Result<MockServer> {
+        MockServer::listen(None, Some(name.to_string()))
+    }
+
+    /// Starts a server that requires `username` and `password` over SCRAM-SHA-256.
+    pub fn start_with_user(username: &str, password: &str) -> io::Result<MockServer> {
+        let salt = thread_rng().gen::<[u8; 16]>().to_vec();
+        let salted_password = pbkdf2(password.as_bytes(), &salt, SCRAM_ITERATIONS);
+        MockServer::listen(Some(User {
+            username: username.to_string(),
+            salt,
+            salted_password,
+        }), None)
+    }
+
+    fn listen(user: Option<User>, replica_set: Option<String>) -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(State {
+            address,
+            user,
+            replica_set,
+            election_id: Mutex::new(ObjectId::new()),
+            faults: Mutex::new(Vec::new()),
+            received: Mutex::new(Vec::new()),
+            streams: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+            next_id: AtomicI32::new(1),
+        });
+
+        let accepting = state.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if accepting.stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                if let Ok(clone) = stream.try_clone() {
+                    lock(&accepting.streams).push(clone);
+                }
+                let serving = accepting.clone();
+                thread::spawn(move || serving.serve(stream));
+            }
+        });
+
+        Ok(MockServer { address, state })
+    }
+
+    /// The address the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The server as a `Host` for `ConnectionOptions`.
+    pub fn host(&self) -> Host {
+        Host {
+            hostname: self.address.ip().to_string(),
+            port: self.address.port(),
+        }
+    }
+
+    /// A connection string for the server.
+    pub fn uri(&self) -> String {
+        format!("mongodb://{}/", self.address)
+    }
+
+    /// Makes the next `times` calls of `command` fail with `fault`.
+    ///
+    /// Command names are case-insensitive.
+    pub fn fail_command(&self, command: &str, fault: Fault, times: usize) {
+        lock(&self.state.faults).push((command.to_string(), fault, times));
+    }
+
+    /// Simulates an election won by this server, which changes the reported `electionId`.
+    pub fn elect(&self) {
+        *lock(&self.state.election_id) = ObjectId::new();
+    }
+
+    /// Names of the commands received so far, in order.
+    pub fn received(&self) -> Vec<String> {
+        lock(&self.state.received).clone()
+    }
+
+    /// How many times `command` was received, ignoring case.
+    pub fn count(&self, command: &str) -> usize {
+        lock(&self.state.received)
+            .iter()
+            .filter(|received| received.eq_ignore_ascii_case(command))
+            .count()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.state.stopped.store(true, Ordering::SeqCst);
+        // Wakes the accepting thread so that it sees the flag.
+        let _ = TcpStream::connect(self.address);
+        for stream in lock(&self.state.streams).drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl State {
+    fn serve(&self, mut stream: TcpStream) {
+        let mut connection = Connection {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            ..Connection::default()
+        };
+        while !self.stopped.load(Ordering::SeqCst) {
+            let (request_id, op_code, body) = match read_message(&mut stream) {
+                Ok(message) => message,
+                Err(_) => break,
+            };
+            let command = match parse_command(op_code, &body) {
+                Some(command) => command,
+                None => break,
+            };
+            let reply = match self.reply(&mut connection, &command) {
+                Some(reply) => reply,
+                None => break,
+            };
+            let reply_op = if op_code == OP_QUERY { OP_REPLY } else { OP_MSG };
+            let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+            if write_message(&mut stream, id, request_id, reply_op, &reply).is_err() {
+                break;
+            }
+        }
+        // A clone is kept for `Drop`, so the socket stays open unless shut down here.
+        let _ = stream.shutdown(Shutdown::Both);
+    }
+
+    // Returns `None` when the connection should be closed.
+    fn reply(&self, connection: &mut Connection, command: &Document) -> Option<Document> {
+        let name = command.keys().next().cloned().unwrap_or_default();
+        lock(&self.received).push(name.clone());
+
+        match self.take_fault(&name) {
+            Some(Fault::CommandError { code, message }) => return Some(error(code, &message)),
+            Some(Fault::CloseConnection) => return None,
+            Some(Fault::Delay(delay)) => thread::sleep(delay),
+            None => {}
+        }
+
+        Some(match name.to_ascii_lowercase().as_str() {
+            "hello" | "ismaster" => self.hello(connection, command),
+            "ping" | "endsessions" => doc! { "ok": 1 },
+            "listdatabases" if self.user.is_some() && !connection.authenticated => {
+                error(13, "Command listDatabases requires authentication")
+            }
+            "listdatabases" => doc! {
+                "databases": [{ "name": "admin", "sizeOnDisk": 0, "empty": false }],
+                "totalSize": 0,
+                "ok": 1,
+            },
+            "saslstart" => self.sasl_start(connection, command),
+            "saslcontinue" => self.sasl_continue(connection, command),
+            _ => error(59, &format!("no such command: '{}'", name)),
+        })
+    }
+
+    fn take_fault(&self, name: &str) -> Option<Fault> {
+        let mut faults = lock(&self.faults);
+        let position = faults
+            .iter()
+            .position(|(command, _, times)| *times > 0 && command.eq_ignore_ascii_case(name))?;
+        faults[position].2 -= 1;
+        Some(faults[position].1.clone())
+    }
+
+    fn hello(&self, connection: &Connection, command: &Document) -> Document {
+        let mut reply = doc! {
+            "helloOk": true,
+            "ismaster": true,
+            "isWritablePrimary": true,
+            "maxBsonObjectSize": 16_777_216,
+            "maxMessageSizeBytes": 48_000_000,
+            "maxWriteBatchSize": 100_000,
+            "localTime": DateTime::now(),
+            "logicalSessionTimeoutMinutes": 30,
+            "connectionId": connection.id,
+            "minWireVersion": 0,
+            "maxWireVersion": 21,
+            "readOnly": false,
+            "ok": 1,
+        };
+        if let Some(ref name) = self.replica_set {
+            let me = self.address.to_string();
+            reply.insert("setName", name.as_str());
+            reply.insert("setVersion", 1);
+            reply.insert("hosts", vec![me.as_str()]);
+            reply.insert("primary", me.as_str());
+            reply.insert("me", me.as_str());
+            reply.insert("electionId", *lock(&self.election_id));
+        }
+        if command.contains_key("saslSupportedMechs") && self.user.is_some() {
+            reply.insert("saslSupportedMechs", vec!["SCRAM-SHA-256"]);
+        }
+        reply
+    }
+
+    fn sasl_start(&self, connection: &mut Connection, command: &Document) -> Document {
+        let user = match self.user {
+            Some(ref user) => user,
+            None => return error(334, "No users are configured"),
+        };
+        if command.get_str("mechanism").ok() != Some("SCRAM-SHA-256") {
+            return error(334, "Only SCRAM-SHA-256 is supported");
+        }
+        let client_first = match payload(command) {
+            Some(payload) => payload,
+            None => return error(17, "Malformed SCRAM payload"),
+        };
+
+        // client-first-message: "n,,n=<user>,r=<nonce>"
+        let client_first_bare = client_first.trim_start_matches("n,,").to_string();
+        let username = attribute(&client_first_bare, 'n').unwrap_or_default();
+        let client_nonce = attribute(&client_first_bare, 'r').unwrap_or_default();
+        if username != user.username || client_nonce.is_empty() {
+            return error(18, "Authentication failed.");
+        }
+
+        let nonce = format!("{}{}", client_nonce, base64::encode(thread_rng().gen::<[u8; 18]>()));
+        let server_first = format!("r={},s={},i={}", nonce, base64::encode(&user.salt), SCRAM_ITERATIONS);
+        connection.scram = Some((format!("{},{}", client_first_bare, server_first), nonce));
+        sasl_reply(false, &server_first)
+    }
+
+    fn sasl_continue(&self, connection: &mut Connection, command: &Document) -> Document {
+        let (user, (auth_prefix, nonce)) = match (self.user.as_ref(), connection.scram.take()) {
+            (Some(user), Some(scram)) => (user, scram),
+            _ => return error(17, "No SASL conversation in progress"),
+        };
+        let client_final = match payload(command) {
+            Some(payload) => payload,
+            None => return error(17, "Malformed SCRAM payload"),
+        };
+
+        // client-final-message: "c=biws,r=<nonce>,p=<proof>"
+        let without_proof = match client_final.rfind(",p=") {
+            Some(end) => &client_final[..end],
+            None => return error(18, "Authentication failed."),
+        };
+        let proof = attribute(&client_final, 'p').and_then(|p| base64::decode(p).ok());
+        let auth_message = format!("{},{}", auth_prefix, without_proof);
+
+        let client_key = hmac(&user.salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let expected_proof: Vec<u8> = client_key.iter().zip(&client_signature).map(|(a, b)| a ^ b).collect();
+        if attribute(&client_final, 'r') != Some(nonce) || proof != Some(expected_proof) {
+            return error(18, "Authentication failed.");
+        }
+
+        connection.authenticated = true;
+        let server_key = hmac(&user.salted_password, b"Server Key");
+        let server_signature = hmac(&server_key, auth_message.as_bytes());
+        sasl_reply(true, &format!("v={}", base64::encode(server_signature)))
+    }
+}
+
+fn read_message(stream: &mut TcpStream) -> io::Result<(i32, i32, Vec<u8>)> {
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header)?;
+    let length = i32_at(&header, 0).unwrap_or(0);
+    let request_id = i32_at(&header, 4).unwrap_or(0);
+    let op_code = i32_at(&header, 12).unwrap_or(0);
+    if length < 16 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad message length"));
+    }
+    let mut body = vec![0u8; length as usize - 16];
+    stream.read_exact(&mut body)?;
+    Ok((request_id, op_code, body))
+}
+
+fn write_message(stream: &mut TcpStream, id: i32, response_to: i32, op_code: i32, reply: &Document) -> io::Result<()> {
+    let mut body = Vec::new();
+    if op_code == OP_MSG {
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.push(0);
+    } else {
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&0i64.to_le_bytes());
+        body.extend_from_slice(&0i32.to_le_bytes());
+        body.extend_from_slice(&1i32.to_le_bytes());
+    }
+    reply
+        .to_writer(&mut body)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let mut message = Vec::with_capacity(16 + body.len());
+    message.extend_from_slice(&(16 + body.len() as i32).to_le_bytes());
+    message.extend_from_slice(&id.to_le_bytes());
+    message.extend_from_slice(&response_to.to_le_bytes());
+    message.extend_from_slice(&op_code.to_le_bytes());
+    message.extend_from_slice(&body);
+    stream.write_all(&message)
+}
+
+// Extracts the command document from an OP_MSG body section or an OP_QUERY query.
+fn parse_command(op_code: i32, body: &[u8]) -> Option<Document> {
+    match op_code {
+        OP_MSG => {
+            // flagBits, then sections; only the kind 0 body section holds the command.
+            let mut position = 4;
+            while position < body.len() {
+                let kind = body[position];
+                position += 1;
+                let size = i32_at(body, position)? as usize;
+                if kind == 0 {
+                    return Document::from_reader(body.get(position..position + size)?).ok();
+                }
+                position += size;
+            }
+            None
+        }
+        OP_QUERY => {
+            // flags, fullCollectionName, numberToSkip, numberToReturn, query
+            let name_end = 4 + body.get(4..)?.iter().position(|&b| b == 0)?;
+            Document::from_reader(body.get(name_end + 9..)?).ok()
+        }
+        _ => None,
+    }
+}
+
+fn i32_at(bytes: &[u8], position: usize) -> Option<i32> {
+    let mut le = [0u8; 4];
+    le.copy_from_slice(bytes.get(position..position + 4)?);
+    Some(i32::from_le_bytes(le))
+}
+
+fn error(code: i32, message: &str) -> Document {
+    doc! { "ok": 0, "errmsg": message, "code": code }
+}
+
+fn payload(command: &Document) -> Option<String> {
+    let bytes = command.get_binary_generic("payload").ok()?;
+    String::from_utf8(bytes.clone()).ok()
+}
+
+fn sasl_reply(done: bool, payload: &str) -> Document {
+    doc! {
+        "conversationId": 1,
+        "done": done,
+        "payload": Bson::Binary(Binary {
+            subtype: BinarySubtype::Generic,
+            bytes: payload.as_bytes().to_vec(),
+        }),
+        "ok": 1,
+    }
+}
+
+// Value of `<key>=` in a comma-separated SCRAM message.
+fn attribute(message: &str, key: char) -> Option<String> {
+    message
+        .split(',')
+        .find_map(|part| part.strip_prefix(key)?.strip_prefix('='))
+        .map(str::to_string)
+}
+
+fn hmac(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+    let mut u = hmac(password, &block);
+    let mut result = u.clone();
+    for _ in 1..iterations {
+        u = hmac(password, &u);
+        for (r, b) in result.iter_mut().zip(&u) {
+            *r ^= b;
+        }
+    }
+    result
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
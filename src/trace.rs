@@ -0,0 +1,134 @@
+//! Spans around connection creation and validation, and configuration reload events.
+//!
+//! Without the `tracing` feature these compile to nothing, so callers need no `cfg`.
+//! Spans carry hosts, the database and the auth mechanism, never credentials.
+use std::path::Path;
+
+use crate::error::Error;
+#[cfg(feature = "tracing")]
+use crate::Auth;
+use crate::{ConnectionOptions, Host};
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Clone)]
+pub(crate) struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    #[cfg(any(feature = "bb8", feature = "deadpool"))]
+    pub(crate) fn current() -> Span {
+        Span
+    }
+
+    pub(crate) fn in_scope<F: FnOnce() -> T, T>(&self, f: F) -> T {
+        f()
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn connect(options: &ConnectionOptions, host: &Host) -> Span {
+    tracing::info_span!(
+        "r2d2_mongodb.connect",
+        host = %address(host),
+        db = %options.db,
+        auth_mechanism = auth_mechanism(options.auth.as_ref()),
+        tls = options.ssl.is_some(),
+        outcome = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn validate(host: &Host) -> Span {
+    tracing::info_span!(
+        "r2d2_mongodb.validate",
+        host = %address(host),
+        outcome = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+/// Covers the first round-trip, where the driver negotiates TLS and authenticates.
+#[cfg(feature = "tracing")]
+pub(crate) fn handshake(options: &ConnectionOptions) -> Span {
+    let ssl = options.ssl.as_ref();
+    tracing::debug_span!(
+        "r2d2_mongodb.handshake",
+        username = options.auth.as_ref().map(|auth| auth.username.as_str()),
+        tls = ssl.is_some(),
+        client_cert = ssl.is_some_and(|ssl| ssl.cert.is_some()),
+        verify_peer = ssl.is_some_and(|ssl| ssl.verify_peer == crate::VerifyPeer::Yes),
+        outcome = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+/// Records whether the work done in `span` succeeded.
+#[cfg(feature = "tracing")]
+pub(crate) fn outcome<T>(span: &Span, result: &Result<T, Error>) {
+    match *result {
+        Ok(_) => {
+            span.record("outcome", "ok");
+        }
+        Err(ref e) => {
+            span.record("outcome", e.kind());
+            span.record("error", tracing::field::display(e));
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn config_applied(path: &Path) {
+    tracing::info!(path = %path.display(), "r2d2_mongodb: applied new connection options");
+}
+
+#[cfg(feature = "tracing")]
+pub(crate) fn config_rejected(path: &Path, error: &Error) {
+    tracing::warn!(
+        path = %path.display(),
+        error = %error,
+        "r2d2_mongodb: rejected connection options, keeping the current ones"
+    );
+}
+
+#[cfg(feature = "tracing")]
+fn address(host: &Host) -> String {
+    format!("{}:{}", host.hostname, host.port)
+}
+
+// Only username and password credentials are configured; the driver negotiates SCRAM.
+#[cfg(feature = "tracing")]
+fn auth_mechanism(auth: Option<&Auth>) -> &'static str {
+    if auth.is_some() {
+        "SCRAM"
+    } else {
+        "none"
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn connect(_options: &ConnectionOptions, _host: &Host) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn validate(_host: &Host) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn handshake(_options: &ConnectionOptions) -> Span {
+    Span
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn outcome<T>(_span: &Span, _result: &Result<T, Error>) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn config_applied(_path: &Path) {}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn config_rejected(_path: &Path, _error: &Error) {}
@@ -0,0 +1,83 @@
+//! Options recognised in connection strings.
+
+// Canonical names of the options defined by the connection string specification.
+const KNOWN: &[&str] = &[
+    "appName",
+    "authMechanism",
+    "authMechanismProperties",
+    "authSource",
+    "compressors",
+    "connectTimeoutMS",
+    "directConnection",
+    "heartbeatFrequencyMS",
+    "journal",
+    "loadBalanced",
+    "localThresholdMS",
+    "maxConnecting",
+    "maxIdleTimeMS",
+    "maxPoolSize",
+    "maxStalenessSeconds",
+    "minPoolSize",
+    "proxyHost",
+    "proxyPassword",
+    "proxyPort",
+    "proxyUsername",
+    "readConcernLevel",
+    "readPreference",
+    "readPreferenceTags",
+    "replicaSet",
+    "retryReads",
+    "retryWrites",
+    "serverMonitoringMode",
+    "serverSelectionTimeoutMS",
+    "serverSelectionTryOnce",
+    "socketTimeoutMS",
+    "srvMaxHosts",
+    "srvServiceName",
+    "timeoutMS",
+    "tls",
+    "tlsAllowInvalidCertificates",
+    "tlsAllowInvalidHostnames",
+    "tlsCAFile",
+    "tlsCertificateKeyFile",
+    "tlsCertificateKeyFilePassword",
+    "tlsDisableCertificateRevocationCheck",
+    "tlsDisableOCSPEndpointCheck",
+    "tlsInsecure",
+    "w",
+    "waitQueueTimeoutMS",
+    "wTimeoutMS",
+    "zlibCompressionLevel",
+];
+
+// Deprecated option names and the options that replace them.
+const DEPRECATED: &[(&str, &str)] = &[
+    ("j", "journal"),
+    ("ssl", "tls"),
+    ("sslAllowInvalidCertificates", "tlsAllowInvalidCertificates"),
+    ("sslAllowInvalidHostnames", "tlsAllowInvalidHostnames"),
+    ("sslCAFile", "tlsCAFile"),
+    ("sslPEMKeyFile", "tlsCertificateKeyFile"),
+    ("sslPEMKeyPassword", "tlsCertificateKeyFilePassword"),
+    ("wtimeout", "wTimeoutMS"),
+];
+
+/// Whether `key` names a current option. Option names are case-insensitive.
+pub(crate) fn is_known(key: &str) -> bool {
+    KNOWN.iter().any(|known| known.eq_ignore_ascii_case(key))
+}
+
+/// The canonical spelling of `key`, with deprecated names resolved to their replacements.
+///
+/// Returns `None` for options that are neither known nor deprecated.
+pub(crate) fn canonical(key: &str) -> Option<&'static str> {
+    replacement(key).or_else(|| KNOWN.iter().find(|known| known.eq_ignore_ascii_case(key)).copied())
+}
+
+/// The option replacing `key`, if `key` is a deprecated name.
+pub(crate) fn replacement(key: &str) -> Option<&'static str> {
+    DEPRECATED
+        .iter()
+        .find(|(deprecated, _)| deprecated.eq_ignore_ascii_case(key))
+        .map(|&(_, replacement)| replacement)
+}
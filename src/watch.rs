@@ -0,0 +1,130 @@
+//! Reloading connection options from a file.
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::trace;
+use crate::{ConnectionOptions, OptionsHandle};
+
+type Parser = Box<dyn Fn(&str) -> Result<ConnectionOptions, Error> + Send>;
+type ErrorHandler = Box<dyn Fn(&Error) + Send>;
+
+/// Applies the connection options in a file to a live manager whenever the file changes
+///
+/// By default the file holds a connection string; `with_parser` reads other formats.
+/// An update that fails to parse or validate is rejected: the manager keeps its
+/// options, and the reason is returned by `check`, passed to `with_error_handler` by a
+/// spawned watcher and logged with the `tracing` feature.
+///
+/// ```rust,no_run
+/// # extern crate r2d2_mongodb;
+/// # use r2d2_mongodb::{ConfigWatcher, MongodbConnectionManager};
+/// let manager = MongodbConnectionManager::new_with_uri("mongodb://localhost:27017").unwrap();
+/// let _watch = ConfigWatcher::new("/etc/app/mongodb-uri", manager.options_handle()).spawn();
+/// ```
+pub struct ConfigWatcher {
+    path: PathBuf,
+    options: OptionsHandle,
+    parser: Parser,
+    interval: Duration,
+    on_error: ErrorHandler,
+    last: Option<String>,
+}
+
+/// Stops a spawned `ConfigWatcher` when dropped.
+pub struct WatchGuard {
+    _stop: Sender<()>,
+}
+
+impl ConfigWatcher {
+    pub fn new<P: AsRef<Path>>(path: P, options: OptionsHandle) -> ConfigWatcher {
+        ConfigWatcher {
+            path: path.as_ref().to_path_buf(),
+            options,
+            parser: Box::new(|contents| ConnectionOptions::from_uri(contents.trim())),
+            interval: Duration::from_secs(5),
+            on_error: Box::new(|_| {}),
+            last: None,
+        }
+    }
+
+    /// Sets how the file's contents are turned into options.
+    ///
+    /// Default: a connection string, parsed with `ConnectionOptions::from_uri`
+    pub fn with_parser<F>(mut self, parser: F) -> ConfigWatcher
+    where
+        F: Fn(&str) -> Result<ConnectionOptions, Error> + Send + 'static,
+    {
+        self.parser = Box::new(parser);
+        self
+    }
+
+    /// Sets how often a spawned watcher reads the file.
+    ///
+    /// Default: `5s`
+    pub fn with_interval(mut self, interval: Duration) -> ConfigWatcher {
+        self.interval = interval;
+        self
+    }
+
+    /// Sets what a spawned watcher does with a file it cannot read or apply.
+    ///
+    /// Default: nothing, besides the `tracing` warning
+    pub fn with_error_handler<F>(mut self, on_error: F) -> ConfigWatcher
+    where
+        F: Fn(&Error) + Send + 'static,
+    {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    /// Reads the file and applies it if it changed since the last check.
+    ///
+    /// Returns whether new options were applied. A rejected file is not retried until
+    /// it changes again.
+    pub fn check(&mut self) -> Result<bool, Error> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                let e = Error::Config(format!("cannot read {}: {}", self.path.display(), e));
+                trace::config_rejected(&self.path, &e);
+                return Err(e);
+            }
+        };
+        if self.last.as_ref() == Some(&contents) {
+            return Ok(false);
+        }
+        let result = (self.parser)(&contents).and_then(|options| self.options.update(options));
+        self.last = Some(contents);
+        match result {
+            Ok(()) => {
+                trace::config_applied(&self.path);
+                Ok(true)
+            }
+            Err(e) => {
+                trace::config_rejected(&self.path, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks the file every `interval` on a background thread until the guard is
+    /// dropped.
+    pub fn spawn(mut self) -> WatchGuard {
+        let (stop, stopped) = mpsc::channel();
+        thread::spawn(move || loop {
+            // The old options stay in place.
+            if let Err(e) = self.check() {
+                (self.on_error)(&e);
+            }
+            match stopped.recv_timeout(self.interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => return,
+            }
+        });
+        WatchGuard { _stop: stop }
+    }
+}
@@ -0,0 +1,170 @@
+#![cfg(all(feature = "test-util", any(feature = "bb8", feature = "deadpool")))]
+extern crate r2d2_mongodb;
+extern crate tokio;
+
+use r2d2_mongodb::test_util::MockServer;
+use r2d2_mongodb::{AsyncMongodbConnectionManager, ConnectionOptions};
+
+fn manager(server: &MockServer) -> AsyncMongodbConnectionManager {
+    AsyncMongodbConnectionManager::new_with_uri(&server.uri()).unwrap()
+}
+
+fn options(server: &MockServer) -> ConnectionOptions {
+    ConnectionOptions::from_uri(&server.uri()).unwrap()
+}
+
+#[cfg(feature = "bb8")]
+mod bb8 {
+    use r2d2_mongodb::bb8::{ManageConnection, Pool};
+    use r2d2_mongodb::test_util::MockServer;
+    use r2d2_mongodb::{ConnectionEventHandler, Error, Host, MongodbConnectionManager};
+
+    use std::future::Future;
+    use std::pin::pin;
+    use std::task::{Context, Poll, Waker};
+
+    use super::{manager, options};
+
+    #[tokio::test]
+    async fn connects_and_validates() {
+        let server = MockServer::start().unwrap();
+        let pool = Pool::builder().max_size(1).build(manager(&server)).await.unwrap();
+
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.host().port, server.address().port());
+        assert!(server.count("listDatabases") >= 1);
+    }
+
+    #[tokio::test]
+    async fn invalid_connections_are_replaced() {
+        let server = MockServer::start().unwrap();
+        let pool = Pool::builder().max_size(1).build(manager(&server)).await.unwrap();
+        drop(pool.get().await.unwrap());
+
+        server.fail_command(
+            "listDatabases",
+            r2d2_mongodb::test_util::Fault::CommandError {
+                code: 8000,
+                message: "injected".to_string(),
+            },
+            1,
+        );
+        pool.get().await.unwrap();
+        assert_eq!(pool.state().statistics.connections_closed_invalid, 1);
+    }
+
+    #[tokio::test]
+    async fn retires_connections_after_reconfiguration() {
+        let old = MockServer::start().unwrap();
+        let new = MockServer::start().unwrap();
+        let manager = manager(&old);
+        let handle = manager.options_handle();
+        let pool = Pool::builder().max_size(1).build(manager).await.unwrap();
+
+        // Checked in before the update, so it is caught by `is_valid` on the next checkout.
+        drop(pool.get().await.unwrap());
+        handle.update(options(&new)).unwrap();
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.host().port, new.address().port());
+        assert_eq!(pool.state().statistics.connections_closed_invalid, 1);
+
+        // Checked in after the update, so it is caught by `has_broken`.
+        let broken = pool.state().statistics.connections_closed_broken;
+        handle.update(options(&old)).unwrap();
+        drop(conn);
+        assert_eq!(pool.state().statistics.connections_closed_broken, broken + 1);
+        assert_eq!(pool.state().connections, 0);
+    }
+
+    #[derive(Debug)]
+    struct Panicking;
+
+    impl ConnectionEventHandler for Panicking {
+        fn on_connect_start(&self, _host: &Host) {
+            panic!("handler failed");
+        }
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "handler failed")]
+    async fn panics_on_the_blocking_thread_are_raised_in_the_caller() {
+        let server = MockServer::start().unwrap();
+        let manager = MongodbConnectionManager::new(options(&server)).with_event_handler(Box::new(Panicking));
+        let _ = super::AsyncMongodbConnectionManager::from(manager).connect().await;
+    }
+
+    #[test]
+    fn connects_cancelled_by_a_runtime_shutdown_fail() {
+        let server = MockServer::start().unwrap();
+        let manager = manager(&server);
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let handle = runtime.handle().clone();
+        runtime.shutdown_background();
+
+        // The blocking task is cancelled right away by the runtime shutting down.
+        let _runtime = handle.enter();
+        let mut connect = pin!(manager.connect());
+        let result = match connect.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(result) => result,
+            Poll::Pending => panic!("still pending"),
+        };
+        assert!(matches!(result, Err(Error::Connect(_))));
+    }
+}
+
+#[cfg(feature = "deadpool")]
+mod deadpool {
+    use r2d2_mongodb::deadpool::managed::Pool;
+    use r2d2_mongodb::test_util::{Fault, MockServer};
+    use r2d2_mongodb::AsyncMongodbConnectionManager;
+
+    use super::{manager, options};
+
+    type MongoPool = Pool<AsyncMongodbConnectionManager>;
+
+    #[tokio::test]
+    async fn creates_and_recycles() {
+        let server = MockServer::start().unwrap();
+        let pool = MongoPool::builder(manager(&server)).max_size(1).build().unwrap();
+
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.host().port, server.address().port());
+        drop(conn);
+        let _conn = pool.get().await.unwrap();
+        assert_eq!(server.count("listDatabases"), 1);
+        assert_eq!(pool.status().size, 1);
+    }
+
+    #[tokio::test]
+    async fn failed_recycles_are_replaced() {
+        let server = MockServer::start().unwrap();
+        let pool = MongoPool::builder(manager(&server)).max_size(1).build().unwrap();
+        let first = pool.get().await.unwrap();
+        let created = first.created_at();
+        drop(first);
+
+        let injected = Fault::CommandError {
+            code: 8000,
+            message: "injected".to_string(),
+        };
+        server.fail_command("listDatabases", injected, 1);
+        let conn = pool.get().await.unwrap();
+        assert!(conn.created_at() > created);
+    }
+
+    #[tokio::test]
+    async fn retires_connections_after_reconfiguration() {
+        let old = MockServer::start().unwrap();
+        let new = MockServer::start().unwrap();
+        let manager = manager(&old);
+        let handle = manager.options_handle();
+        let pool = MongoPool::builder(manager).max_size(1).build().unwrap();
+        drop(pool.get().await.unwrap());
+
+        handle.update(options(&new)).unwrap();
+        let conn = pool.get().await.unwrap();
+        assert_eq!(conn.host().port, new.address().port());
+        // Retired without a round-trip to the old server.
+        assert_eq!(old.count("listDatabases"), 0);
+    }
+}
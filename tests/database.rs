@@ -0,0 +1,38 @@
+extern crate r2d2;
+extern crate r2d2_mongodb;
+
+use r2d2::ManageConnection;
+use r2d2_mongodb::mongodb::options::{Acknowledgment, ReadConcern, ReadPreference, SelectionCriteria, WriteConcern};
+use r2d2_mongodb::{ConnectionOptions, MongodbConnectionManager};
+
+// The driver connects lazily, so no server is needed.
+#[test]
+fn pooled_databases_use_the_configured_defaults() {
+    let write_concern = WriteConcern::builder().w(Acknowledgment::Majority).build();
+    let options = ConnectionOptions::builder()
+        .with_host("localhost", 27017)
+        .with_db("mydb")
+        .with_read_concern(ReadConcern::majority())
+        .with_write_concern(write_concern.clone())
+        .with_read_preference(ReadPreference::Primary)
+        .build();
+    let conn = MongodbConnectionManager::new(options).connect().unwrap();
+
+    assert_eq!(conn.name(), "mydb");
+    assert_eq!(conn.read_concern(), Some(&ReadConcern::majority()));
+    assert_eq!(conn.write_concern(), Some(&write_concern));
+    assert!(matches!(
+        conn.selection_criteria(),
+        Some(SelectionCriteria::ReadPreference(ReadPreference::Primary))
+    ));
+}
+
+#[test]
+fn pooled_databases_default_to_the_client_settings() {
+    let options = ConnectionOptions::builder().with_host("localhost", 27017).build();
+    let conn = MongodbConnectionManager::new(options).connect().unwrap();
+
+    assert_eq!(conn.read_concern(), None);
+    assert_eq!(conn.write_concern(), None);
+    assert!(conn.selection_criteria().is_none());
+}
@@ -0,0 +1,90 @@
+extern crate r2d2_mongodb;
+
+use r2d2_mongodb::{ConnectionOptions, Error, VerifyPeer};
+
+use std::env;
+use std::fs;
+
+// Each test uses its own prefix, so they can run in parallel.
+fn set(prefix: &str, vars: &[(&str, &str)]) {
+    for &(name, value) in vars {
+        env::set_var(format!("{}_{}", prefix, name), value);
+    }
+}
+
+fn malformed(result: Result<ConnectionOptions, Error>) -> Vec<String> {
+    match result {
+        Err(Error::Env(errors)) => errors.into_iter().map(|e| e.variable).collect(),
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("expected an error"),
+    }
+}
+
+#[test]
+fn discrete_variables_override_the_uri() {
+    let password_file = env::temp_dir().join("r2d2_mongodb_env_test_password");
+    fs::write(&password_file, "from-file\n").unwrap();
+    set(
+        "ENV_OVERRIDE",
+        &[
+            ("URI", "mongodb://user:pw@db1:27018/uridb"),
+            ("HOSTS", "db2, db3:27019"),
+            ("PASSWORD_FILE", password_file.to_str().unwrap()),
+            ("TLS_CA_FILE", "ca.pem"),
+            ("TLS_VERIFY_PEER", "false"),
+        ],
+    );
+
+    let options = ConnectionOptions::from_env("ENV_OVERRIDE").unwrap();
+    assert_eq!(options.db, "uridb");
+    let hosts: Vec<_> = options.hosts.iter().map(|h| (h.hostname.as_str(), h.port)).collect();
+    assert_eq!(hosts, vec![("db2", 27017), ("db3", 27019)]);
+    let auth = options.auth.unwrap();
+    assert_eq!((auth.username.as_str(), auth.password.as_str()), ("user", "from-file"));
+    let ssl = options.ssl.unwrap();
+    assert_eq!(ssl.ca_file.as_deref(), Some("ca.pem"));
+    assert!(ssl.verify_peer == VerifyPeer::No);
+}
+
+#[test]
+fn discrete_variables_alone() {
+    set("ENV_DISCRETE", &[("HOSTS", "localhost"), ("DB", "mydb")]);
+
+    let options = ConnectionOptions::from_env("ENV_DISCRETE").unwrap();
+    assert_eq!(options.db, "mydb");
+    assert_eq!(options.hosts[0].hostname, "localhost");
+    assert!(options.auth.is_none());
+    assert!(options.ssl.is_none());
+}
+
+#[test]
+fn every_malformed_variable_is_reported() {
+    set(
+        "ENV_MALFORMED",
+        &[
+            ("HOSTS", "db1:notaport,,db2"),
+            ("USERNAME", "root"),
+            ("TLS_CERT_FILE", "client.pem"),
+            ("TLS_VERIFY_PEER", "maybe"),
+        ],
+    );
+
+    assert_eq!(
+        malformed(ConnectionOptions::from_env("ENV_MALFORMED")),
+        vec![
+            "ENV_MALFORMED_HOSTS",
+            "ENV_MALFORMED_HOSTS",
+            "ENV_MALFORMED_USERNAME",
+            "ENV_MALFORMED_TLS_VERIFY_PEER",
+            "ENV_MALFORMED_TLS_CERT_FILE",
+        ]
+    );
+}
+
+#[test]
+fn hosts_or_uri_are_required() {
+    assert_eq!(malformed(ConnectionOptions::from_env("ENV_EMPTY")), vec!["ENV_EMPTY_HOSTS"]);
+
+    set("ENV_BAD_URI", &[("URI", "mongo://db1")]);
+    assert_eq!(malformed(ConnectionOptions::from_env("ENV_BAD_URI")), vec!["ENV_BAD_URI_URI"]);
+}
@@ -0,0 +1,155 @@
+extern crate r2d2;
+extern crate r2d2_mongodb;
+
+use r2d2::ManageConnection;
+use r2d2_mongodb::{CircuitBreaker, ConnectionEventHandler, ConnectionOptions, Error, Host, MongodbConnectionManager};
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl ConnectionEventHandler for Recorder {
+    fn on_connect_start(&self, host: &Host) {
+        self.0.lock().unwrap().push(format!("start {}:{}", host.hostname, host.port));
+    }
+
+    fn on_connect_success(&self, host: &Host, _duration: Duration) {
+        self.0.lock().unwrap().push(format!("success {}:{}", host.hostname, host.port));
+    }
+
+    fn on_connect_failure(&self, host: &Host, error: &Error) {
+        self.0.lock().unwrap().push(format!("failure {}:{} {}", host.hostname, host.port, kind(error)));
+    }
+
+    fn on_validation_failure(&self, host: &Host, error: &Error) {
+        self.0.lock().unwrap().push(format!("invalid {}:{} {}", host.hostname, host.port, kind(error)));
+    }
+
+    fn on_discard(&self, host: &Host) {
+        self.0.lock().unwrap().push(format!("discard {}:{}", host.hostname, host.port));
+    }
+}
+
+fn kind(error: &Error) -> &'static str {
+    match *error {
+        Error::Connect(_) => "connect",
+        Error::Auth(_) => "auth",
+        Error::Validation(_) => "validation",
+        Error::CircuitOpen(_) => "circuit_open",
+        _ => "other",
+    }
+}
+
+#[test]
+fn reports_connect_events_with_chosen_host() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let options = ConnectionOptions::builder().with_host("127.0.0.1", 1).build();
+    let manager = MongodbConnectionManager::new(options).with_event_handler(Box::new(Recorder(events.clone())));
+
+    // The driver connects lazily, so no server is needed to create a client.
+    let conn = manager.connect().unwrap();
+    assert_eq!(conn.host().port, 1);
+    assert_eq!(*events.lock().unwrap(), vec!["start 127.0.0.1:1", "success 127.0.0.1:1"]);
+}
+
+#[test]
+fn no_events_without_hosts() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let options = ConnectionOptions::builder().build();
+    let manager = MongodbConnectionManager::new(options).with_event_handler(Box::new(Recorder(events.clone())));
+
+    assert!(manager.connect().is_err());
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[test]
+fn reports_connect_failures_and_open_circuits() {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    // Nothing listens on port 1, and the handshake makes `connect` reach the server.
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", 1)
+        .with_handshake(true)
+        .with_connect_timeout(Duration::from_millis(200))
+        .build();
+    let breaker = CircuitBreaker {
+        failure_threshold: 1,
+        cool_down: Duration::from_secs(60),
+    };
+    let manager = MongodbConnectionManager::new(options)
+        .with_event_handler(Box::new(Recorder(events.clone())))
+        .with_circuit_breaker(breaker);
+
+    assert!(manager.connect().is_err());
+    assert!(matches!(manager.connect(), Err(Error::CircuitOpen(_))));
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["start 127.0.0.1:1", "failure 127.0.0.1:1 connect", "failure 127.0.0.1:1 circuit_open"]
+    );
+}
+
+#[cfg(feature = "test-util")]
+mod with_server {
+    use r2d2::ManageConnection;
+    use r2d2_mongodb::test_util::{Fault, MockServer};
+    use r2d2_mongodb::{ConnectionOptions, MongodbConnectionManager};
+
+    use std::sync::{Arc, Mutex};
+
+    use super::Recorder;
+
+    #[test]
+    fn reports_auth_failures() {
+        let server = MockServer::start_with_user("root", "secret").unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let options = ConnectionOptions::builder()
+            .with_host("127.0.0.1", server.address().port())
+            .with_auth("root", "wrong")
+            .with_handshake(true)
+            .build();
+        let manager = MongodbConnectionManager::new(options).with_event_handler(Box::new(Recorder(events.clone())));
+
+        assert!(manager.connect().is_err());
+        let failure = format!("failure 127.0.0.1:{} auth", server.address().port());
+        assert_eq!(events.lock().unwrap().last(), Some(&failure));
+    }
+
+    #[test]
+    fn reports_validation_failures() {
+        let server = MockServer::start().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let manager = MongodbConnectionManager::new_with_uri(&server.uri())
+            .unwrap()
+            .with_event_handler(Box::new(Recorder(events.clone())));
+        let mut conn = manager.connect().unwrap();
+
+        let injected = Fault::CommandError {
+            code: 8000,
+            message: "injected".to_string(),
+        };
+        server.fail_command("listDatabases", injected, 1);
+        assert!(manager.is_valid(&mut conn).is_err());
+        let invalid = format!("invalid {}:{} validation", conn.host().hostname, conn.host().port);
+        assert_eq!(events.lock().unwrap().last(), Some(&invalid));
+    }
+
+    #[test]
+    fn reports_discarded_connections() {
+        let server = MockServer::start().unwrap();
+        let other = MockServer::start().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let manager = MongodbConnectionManager::new_with_uri(&server.uri())
+            .unwrap()
+            .with_event_handler(Box::new(Recorder(events.clone())));
+        let mut conn = manager.connect().unwrap();
+
+        manager
+            .options_handle()
+            .update(ConnectionOptions::from_uri(&other.uri()).unwrap())
+            .unwrap();
+        assert!(manager.has_broken(&mut conn));
+        let discard = format!("discard {}:{}", conn.host().hostname, conn.host().port);
+        assert_eq!(events.lock().unwrap().last(), Some(&discard));
+    }
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "test-util")]
+extern crate r2d2;
+extern crate r2d2_mongodb;
+
+use r2d2::{ManageConnection, Pool};
+use r2d2_mongodb::test_util::FaultInjectingManager;
+use r2d2_mongodb::{ConnectionOptions, Error, MongodbConnectionManager};
+
+use std::time::{Duration, Instant};
+
+// Clients connect lazily, so nothing listens on these ports.
+fn manager(ports: &[u16]) -> FaultInjectingManager<MongodbConnectionManager> {
+    let mut builder = ConnectionOptions::builder();
+    for &port in ports {
+        builder.with_host("127.0.0.1", port);
+    }
+    FaultInjectingManager::new(MongodbConnectionManager::new(builder.build()))
+}
+
+#[test]
+fn fails_the_requested_number_of_connects() {
+    let manager = manager(&[1]);
+    manager.handle().fail_connects(2);
+
+    assert!(matches!(manager.connect(), Err(Error::Connect(_))));
+    assert!(matches!(manager.connect(), Err(Error::Connect(_))));
+    assert!(manager.connect().is_ok());
+}
+
+#[test]
+fn forces_validation_results() {
+    let manager = manager(&[1]);
+    let faults = manager.handle();
+    let mut conn = manager.connect().unwrap();
+
+    faults.set_valid(Some(false));
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::Validation(_))));
+    faults.set_valid(Some(true));
+    assert!(manager.is_valid(&mut conn).is_ok());
+    faults.set_broken(Some(true));
+    assert!(manager.has_broken(&mut conn));
+}
+
+#[test]
+fn fails_connections_to_several_hosts() {
+    let manager = manager(&[1, 2, 3]);
+    let faults = manager.handle();
+    faults.fail_host("127.0.0.1", 1);
+    faults.fail_host("127.0.0.1", 2);
+    faults.fail_host("127.0.0.1", 2);
+
+    // Hosts are picked at random; only connects to the remaining host succeed.
+    let mut connected = 0;
+    for _ in 0..200 {
+        match manager.connect() {
+            Ok(conn) => {
+                assert_eq!(conn.host().port, 3);
+                connected += 1;
+            }
+            Err(e) => assert!(matches!(e, Error::Connect(_))),
+        }
+    }
+    assert!(connected > 0);
+}
+
+#[test]
+fn adds_latency_and_controls_a_pool() {
+    let manager = manager(&[1]);
+    let faults = manager.handle();
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .connection_timeout(Duration::from_millis(200))
+        .build_unchecked(manager);
+
+    faults.fail_connects(usize::MAX);
+    assert!(pool.get().is_err());
+
+    faults.clear();
+    // Nothing listens on the port, so skip the health checks on check out and check in.
+    faults.set_valid(Some(true));
+    faults.set_broken(Some(false));
+    faults.set_latency(Some(Duration::from_millis(20)));
+    let started = Instant::now();
+    assert!(pool.get().is_ok());
+    assert!(started.elapsed() >= Duration::from_millis(20));
+}
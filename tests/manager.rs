@@ -0,0 +1,511 @@
+#![cfg(feature = "test-util")]
+extern crate r2d2;
+extern crate r2d2_mongodb;
+
+use r2d2::ManageConnection;
+use r2d2_mongodb::mongodb::options::ReadConcern;
+use r2d2_mongodb::test_util::{Fault, MockServer};
+use r2d2_mongodb::{
+    CircuitBreaker, ConnectionOptions, Error, MongodbConnectionManager, PoolConfig, RetryPolicy, RetryableError,
+};
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn manager(server: &MockServer) -> MongodbConnectionManager {
+    MongodbConnectionManager::new_with_uri(&server.uri()).unwrap()
+}
+
+#[test]
+fn connects_and_validates() {
+    let server = MockServer::start().unwrap();
+    let manager = manager(&server);
+
+    let mut conn = manager.connect().unwrap();
+    assert_eq!(conn.host().port, server.address().port());
+    manager.is_valid(&mut conn).unwrap();
+    assert!(!manager.has_broken(&mut conn));
+    assert_eq!(server.count("listDatabases"), 1);
+}
+
+#[test]
+fn authenticates_with_scram() {
+    let server = MockServer::start_with_user("root", "secret").unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_auth("root", "secret")
+        .build();
+    let manager = MongodbConnectionManager::new(options);
+
+    let mut conn = manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+    assert_eq!(server.count("saslContinue"), 1);
+}
+
+#[test]
+fn wrong_password_is_an_auth_error() {
+    let server = MockServer::start_with_user("root", "secret").unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_auth("root", "wrong")
+        .build();
+    let manager = MongodbConnectionManager::new(options);
+
+    let mut conn = manager.connect().unwrap();
+    match manager.is_valid(&mut conn) {
+        Err(Error::Auth(_)) => {}
+        other => panic!("expected an auth error, got {:?}", other.err()),
+    }
+}
+
+#[test]
+fn scripted_failure_fails_validation() {
+    let server = MockServer::start().unwrap();
+    let manager = manager(&server);
+    let mut conn = manager.connect().unwrap();
+
+    server.fail_command(
+        "listDatabases",
+        Fault::CommandError {
+            code: 8000,
+            message: "injected".to_string(),
+        },
+        1,
+    );
+    match manager.is_valid(&mut conn) {
+        Err(Error::Validation(_)) => {}
+        other => panic!("expected a validation error, got {:?}", other.err()),
+    }
+    manager.is_valid(&mut conn).unwrap();
+}
+
+#[test]
+fn closed_connections_fail_validation() {
+    let server = MockServer::start().unwrap();
+    let manager = manager(&server);
+    let mut conn = manager.connect().unwrap();
+
+    // Reads are retried once by the driver.
+    server.fail_command("listDatabases", Fault::CloseConnection, 2);
+    let started = Instant::now();
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::Validation(_))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn picks_a_host_from_the_list() {
+    let first = MockServer::start().unwrap();
+    let second = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", first.address().port())
+        .with_host("127.0.0.1", second.address().port())
+        .build();
+    let manager = MongodbConnectionManager::new(options);
+
+    let ports: Vec<u16> = (0..20).map(|_| manager.connect().unwrap().host().port).collect();
+    assert!(ports.contains(&first.address().port()));
+    assert!(ports.contains(&second.address().port()));
+}
+
+#[test]
+fn circuit_breaker_fails_fast_then_probes() {
+    let server = MockServer::start().unwrap();
+    let manager = manager(&server).with_circuit_breaker(CircuitBreaker {
+        failure_threshold: 2,
+        cool_down: Duration::from_millis(200),
+    });
+    let mut conn = manager.connect().unwrap();
+
+    let injected = Fault::CommandError {
+        code: 8000,
+        message: "injected".to_string(),
+    };
+    server.fail_command("listDatabases", injected, 2);
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::Validation(_))));
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::Validation(_))));
+
+    // Open: nothing reaches the server.
+    let calls = server.count("listDatabases");
+    assert!(matches!(manager.connect(), Err(Error::CircuitOpen(_))));
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::CircuitOpen(_))));
+    assert_eq!(server.count("listDatabases"), calls);
+
+    // Half-open: the next connect validates a probe and closes the circuit.
+    thread::sleep(Duration::from_millis(250));
+    manager.connect().unwrap();
+    assert_eq!(server.count("listDatabases"), calls + 1);
+    manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+}
+
+#[test]
+fn circuit_breaker_prefers_healthy_hosts() {
+    let healthy = MockServer::start().unwrap();
+    let failing = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", healthy.address().port())
+        .with_host("127.0.0.1", failing.address().port())
+        .build();
+    let manager = MongodbConnectionManager::new(options).with_circuit_breaker(CircuitBreaker {
+        failure_threshold: 1,
+        cool_down: Duration::from_secs(60),
+    });
+
+    let injected = Fault::CommandError {
+        code: 8000,
+        message: "injected".to_string(),
+    };
+    failing.fail_command("listDatabases", injected, 1);
+    let mut conn = loop {
+        let conn = manager.connect().unwrap();
+        if conn.host().port == failing.address().port() {
+            break conn;
+        }
+    };
+    assert!(manager.is_valid(&mut conn).is_err());
+
+    for _ in 0..10 {
+        assert_eq!(manager.connect().unwrap().host().port, healthy.address().port());
+    }
+}
+
+#[test]
+fn retries_until_the_circuit_half_opens() {
+    let server = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_retry(RetryPolicy {
+            attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            retryable: vec![RetryableError::CircuitOpen],
+            ..RetryPolicy::default()
+        })
+        .build();
+    let manager = MongodbConnectionManager::new(options).with_circuit_breaker(CircuitBreaker {
+        failure_threshold: 1,
+        cool_down: Duration::from_millis(150),
+    });
+
+    let mut conn = manager.connect().unwrap();
+    let injected = Fault::CommandError {
+        code: 8000,
+        message: "injected".to_string(),
+    };
+    server.fail_command("listDatabases", injected, 1);
+    assert!(manager.is_valid(&mut conn).is_err());
+
+    // The first attempt is rejected by the open circuit; a later one probes and succeeds.
+    manager.connect().unwrap();
+}
+
+#[test]
+fn fails_over_to_another_host() {
+    let server = MockServer::start().unwrap();
+    // Nothing listens on port 1.
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", 1)
+        .with_host("127.0.0.1", server.address().port())
+        .with_handshake(true)
+        .with_connect_timeout(Duration::from_millis(200))
+        .with_retry(RetryPolicy {
+            attempts: 2,
+            initial_backoff: Duration::from_millis(10),
+            retryable: vec![RetryableError::Connect],
+            ..RetryPolicy::default()
+        })
+        .build();
+    let manager = MongodbConnectionManager::new(options);
+
+    // Whenever the first attempt picks the unreachable host, the second one avoids it.
+    for _ in 0..10 {
+        assert_eq!(manager.connect().unwrap().host().port, server.address().port());
+    }
+}
+
+#[test]
+fn retries_stop_at_the_pool_timeout() {
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", 1)
+        .with_handshake(true)
+        .with_connect_timeout(Duration::from_secs(10))
+        .with_retry(RetryPolicy {
+            attempts: 100,
+            initial_backoff: Duration::from_millis(10),
+            retryable: vec![RetryableError::Connect],
+            ..RetryPolicy::default()
+        })
+        .build();
+    let manager = MongodbConnectionManager::new(options).with_pool_timeout(Duration::from_millis(300));
+
+    let started = Instant::now();
+    assert!(matches!(manager.connect(), Err(Error::Connect(_))));
+    assert!(started.elapsed() < Duration::from_secs(2), "{:?}", started.elapsed());
+}
+
+#[test]
+fn clients_keep_the_configured_timeouts_after_a_late_handshake() {
+    let server = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_handshake(true)
+        .with_heartbeat_frequency(Duration::from_millis(500))
+        .build();
+    let manager = MongodbConnectionManager::new(options).with_pool_timeout(Duration::from_millis(500));
+    // The handshake leaves little of the pool timeout.
+    server.fail_command("ping", Fault::Delay(Duration::from_millis(300)), 1);
+    let mut conn = manager.connect().unwrap();
+
+    // The server is unknown for a few heartbeats, longer than the time that was left.
+    for command in &["hello", "isMaster"] {
+        server.fail_command(command, Fault::CloseConnection, 8);
+    }
+    assert!(eventually(|| manager.has_broken(&mut conn)));
+    assert!(manager.is_valid(&mut conn).is_ok());
+}
+
+#[test]
+fn gives_up_after_the_last_attempt() {
+    let server = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_retry(RetryPolicy {
+            attempts: 2,
+            initial_backoff: Duration::from_millis(10),
+            retryable: vec![RetryableError::CircuitOpen],
+            ..RetryPolicy::default()
+        })
+        .build();
+    let manager = MongodbConnectionManager::new(options).with_circuit_breaker(CircuitBreaker {
+        failure_threshold: 1,
+        cool_down: Duration::from_secs(60),
+    });
+
+    let mut conn = manager.connect().unwrap();
+    let injected = Fault::CommandError {
+        code: 8000,
+        message: "injected".to_string(),
+    };
+    server.fail_command("listDatabases", injected, 1);
+    assert!(manager.is_valid(&mut conn).is_err());
+    assert!(matches!(manager.connect(), Err(Error::CircuitOpen(_))));
+}
+
+#[test]
+fn handshake_connects_and_authenticates_eagerly() {
+    let server = MockServer::start_with_user("root", "secret").unwrap();
+    let mut builder = ConnectionOptions::builder();
+    builder
+        .with_host("127.0.0.1", server.address().port())
+        .with_auth("root", "secret")
+        .with_handshake(true);
+    let manager = MongodbConnectionManager::new(builder.build());
+    manager.connect().unwrap();
+    assert_eq!(server.count("ping"), 1);
+    assert_eq!(server.count("saslContinue"), 1);
+
+    builder.with_auth("root", "wrong");
+    let manager = MongodbConnectionManager::new(builder.build());
+    assert!(matches!(manager.connect(), Err(Error::Auth(_))));
+}
+
+#[test]
+fn handshake_connections_within_a_pool_timeout_are_kept() {
+    let server = MockServer::start().unwrap();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_handshake(true)
+        .build();
+    let manager = MongodbConnectionManager::new(options.clone()).with_pool_timeout(Duration::from_secs(30));
+    let mut conn = manager.connect().unwrap();
+    assert!(!manager.has_broken(&mut conn));
+    assert!(manager.is_valid(&mut conn).is_ok());
+
+    let config = PoolConfig {
+        max_size: 1,
+        min_idle: Some(0),
+        ..PoolConfig::default()
+    };
+    let pool = config.build(MongodbConnectionManager::new(options)).unwrap();
+    for _ in 0..5 {
+        drop(pool.get().unwrap());
+    }
+    // One more handshake, from the connection above; the pooled one is reused.
+    assert_eq!(server.count("ping"), 2);
+}
+
+#[test]
+fn handshake_fails_fast_on_unreachable_hosts() {
+    // Nothing listens on a port that was just released.
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", port)
+        .with_handshake(true)
+        .with_connect_timeout(Duration::from_millis(300))
+        .build();
+    let manager = MongodbConnectionManager::new(options);
+
+    let started = std::time::Instant::now();
+    assert!(matches!(manager.connect(), Err(Error::Connect(_))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+// Topology changes are noticed by the driver's heartbeats, which these tests speed up.
+fn monitored(server: &MockServer) -> ConnectionOptions {
+    ConnectionOptions::builder()
+        .with_host("127.0.0.1", server.address().port())
+        .with_heartbeat_frequency(Duration::from_millis(500))
+        .with_connect_timeout(Duration::from_millis(300))
+        .build()
+}
+
+fn eventually(mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn retires_connections_after_an_election() {
+    let server = MockServer::start_replica_set("rs0").unwrap();
+    let manager = MongodbConnectionManager::new(monitored(&server));
+    let mut conn = manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+
+    assert!(!manager.has_broken(&mut conn));
+    server.elect();
+    assert!(eventually(|| manager.has_broken(&mut conn)));
+
+    let mut conn = manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+    assert!(!manager.has_broken(&mut conn));
+}
+
+#[test]
+fn handshake_records_the_topology_at_creation() {
+    let server = MockServer::start_replica_set("rs0").unwrap();
+    let mut options = monitored(&server);
+    options.handshake = true;
+    let manager = MongodbConnectionManager::new(options);
+    let mut conn = manager.connect().unwrap();
+
+    server.elect();
+    assert!(eventually(|| manager.has_broken(&mut conn)));
+}
+
+#[test]
+fn has_broken_does_no_io() {
+    let server = MockServer::start_replica_set("rs0").unwrap();
+    let manager = MongodbConnectionManager::new(monitored(&server));
+    let mut conn = manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+
+    // A check-in that asked the server would wait out the delay.
+    server.fail_command("hello", Fault::Delay(Duration::from_secs(1)), 1);
+    server.fail_command("isMaster", Fault::Delay(Duration::from_secs(1)), 1);
+    let started = Instant::now();
+    manager.has_broken(&mut conn);
+    assert!(started.elapsed() < Duration::from_millis(500));
+}
+
+#[test]
+fn retires_connections_when_the_server_is_gone() {
+    let server = MockServer::start().unwrap();
+    let manager = MongodbConnectionManager::new(monitored(&server));
+    let mut conn = manager.connect().unwrap();
+    manager.is_valid(&mut conn).unwrap();
+    assert!(!manager.has_broken(&mut conn));
+
+    drop(server);
+    assert!(eventually(|| manager.has_broken(&mut conn)));
+}
+
+#[test]
+fn reconfigures_a_live_manager() {
+    let old = MockServer::start().unwrap();
+    let new = MockServer::start().unwrap();
+    let manager = manager(&old);
+    let handle = manager.options_handle();
+    let mut conn = manager.connect().unwrap();
+
+    let options = ConnectionOptions::from_uri(&new.uri()).unwrap();
+    handle.update(options).unwrap();
+    assert_eq!(manager.options().hosts[0].port, new.address().port());
+    assert!(manager.has_broken(&mut conn));
+    assert!(manager.is_valid(&mut conn).is_err());
+
+    let mut conn = manager.connect().unwrap();
+    assert_eq!(conn.host().port, new.address().port());
+    assert!(!manager.has_broken(&mut conn));
+}
+
+#[test]
+fn pools_never_hand_out_retired_connections() {
+    let old = MockServer::start().unwrap();
+    let new = MockServer::start().unwrap();
+    let manager = manager(&old);
+    let handle = manager.options_handle();
+    let config = PoolConfig {
+        max_size: 1,
+        min_idle: Some(0),
+        ..PoolConfig::default()
+    };
+    let pool = config.build(manager).unwrap();
+    // Idle in the pool when the options change.
+    drop(pool.get().unwrap());
+
+    handle.update(ConnectionOptions::from_uri(&new.uri()).unwrap()).unwrap();
+    let conn = pool.get().unwrap();
+    assert_eq!(conn.host().port, new.address().port());
+    // Without `test_on_check_out`, checking out does not ask the server.
+    assert_eq!(old.count("listDatabases") + new.count("listDatabases"), 0);
+}
+
+#[test]
+fn retires_connections_when_credentials_change() {
+    let server = MockServer::start_with_user("root", "secret").unwrap();
+    let mut builder = ConnectionOptions::builder();
+    builder
+        .with_host("127.0.0.1", server.address().port())
+        .with_auth("root", "secret");
+    let manager = MongodbConnectionManager::new(builder.build());
+    let mut conn = manager.connect().unwrap();
+    assert!(!manager.has_broken(&mut conn));
+
+    builder.with_auth("root", "rotated");
+    manager.options_handle().update(builder.build()).unwrap();
+    assert!(manager.has_broken(&mut conn));
+    assert!(matches!(manager.is_valid(&mut conn), Err(Error::Retired(ref host)) if host.port == server.address().port()));
+}
+
+#[test]
+fn compares_client_settings_but_not_concerns() {
+    let server = MockServer::start().unwrap();
+    let mut builder = ConnectionOptions::builder();
+    builder.with_host("127.0.0.1", server.address().port());
+    let manager = MongodbConnectionManager::new(builder.build());
+    let handle = manager.options_handle();
+    let mut conn = manager.connect().unwrap();
+
+    let mut options = builder.build();
+    options.read_concern = Some(ReadConcern::majority());
+    handle.update(options).unwrap();
+    assert!(!manager.has_broken(&mut conn));
+
+    builder.with_connect_timeout(Duration::from_secs(1));
+    handle.update(builder.build()).unwrap();
+    assert!(manager.has_broken(&mut conn));
+}
+
+#[test]
+fn rejects_options_without_hosts() {
+    let server = MockServer::start().unwrap();
+    let manager = manager(&server);
+    let update = manager.options_handle().update(ConnectionOptions::builder().build());
+    assert!(matches!(update, Err(Error::Config(_))));
+    assert_eq!(manager.options().hosts[0].port, server.address().port());
+}
@@ -0,0 +1,147 @@
+#![cfg(feature = "metrics")]
+extern crate r2d2;
+extern crate r2d2_mongodb;
+
+use r2d2::{ManageConnection, Pool};
+use r2d2_mongodb::{CircuitBreaker, ConnectionOptions, MongodbConnectionManager, PoolMetrics};
+
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn renders_openmetrics_text() {
+    let metrics = PoolMetrics::new();
+    let options = ConnectionOptions::builder().with_host("127.0.0.1", 1).build();
+    let manager = MongodbConnectionManager::new(options).with_event_handler(Box::new(metrics.clone()));
+    manager.connect().unwrap();
+
+    // No connections are opened with `min_idle` at zero.
+    let pool = Pool::builder()
+        .min_idle(Some(0))
+        .event_handler(Box::new(metrics.clone()))
+        .build_unchecked(manager);
+    let text = metrics.render(&pool.state());
+
+    assert!(text.contains("# TYPE r2d2_mongodb_connect_duration_seconds histogram\n"));
+    assert!(text.contains("r2d2_mongodb_connect_duration_seconds_bucket{host=\"127.0.0.1:1\",le=\"+Inf\"} 1\n"));
+    assert!(text.contains("r2d2_mongodb_connect_duration_seconds_count{host=\"127.0.0.1:1\"} 1\n"));
+    assert!(text.contains("r2d2_mongodb_checkout_wait_seconds_count 0\n"));
+    assert!(text.contains("r2d2_mongodb_connections{state=\"in_use\"} 0\n"));
+    assert!(text.ends_with("# EOF\n"));
+}
+
+#[test]
+fn counts_connect_failures_by_kind() {
+    let metrics = PoolMetrics::new();
+    // Nothing listens on a port that was just released.
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let options = ConnectionOptions::builder()
+        .with_host("127.0.0.1", port)
+        .with_handshake(true)
+        .with_connect_timeout(Duration::from_millis(200))
+        .build();
+    let manager = MongodbConnectionManager::new(options)
+        .with_event_handler(Box::new(metrics.clone()))
+        .with_circuit_breaker(CircuitBreaker {
+            failure_threshold: 1,
+            cool_down: Duration::from_secs(60),
+        });
+    assert!(manager.connect().is_err());
+    assert!(manager.connect().is_err());
+
+    let pool = Pool::builder().min_idle(Some(0)).build_unchecked(manager);
+    let text = metrics.render(&pool.state());
+    let host = format!("host=\"127.0.0.1:{}\"", port);
+    assert!(text.contains(&format!("r2d2_mongodb_connect_failures_total{{{},kind=\"connect\"}} 1\n", host)), "{}", text);
+    assert!(text.contains(&format!("r2d2_mongodb_connect_failures_total{{{},kind=\"circuit_open\"}} 1\n", host)), "{}", text);
+}
+
+#[test]
+fn records_checkout_waits_and_timeouts() {
+    let metrics = PoolMetrics::new();
+    let options = ConnectionOptions::builder().with_host("127.0.0.1", 1).build();
+    // Clients connect lazily and are not tested, so no server is needed.
+    let pool = Pool::builder()
+        .max_size(1)
+        .min_idle(Some(0))
+        .test_on_check_out(false)
+        .connection_timeout(Duration::from_millis(50))
+        .event_handler(Box::new(metrics.clone()))
+        .build_unchecked(MongodbConnectionManager::new(options));
+
+    let held = pool.get().unwrap();
+    assert!(pool.get().is_err());
+
+    thread::scope(|scope| {
+        scope.spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(held);
+        });
+        // Waits for the connection held above.
+        pool.get_timeout(Duration::from_secs(5)).unwrap();
+    });
+
+    let text = metrics.render(&pool.state());
+    assert!(text.contains("r2d2_mongodb_checkout_timeouts_total 1\n"), "{}", text);
+    assert!(text.contains("r2d2_mongodb_checkout_wait_seconds_bucket{le=\"0.05\"} 1\n"), "{}", text);
+    assert!(text.contains("r2d2_mongodb_checkout_wait_seconds_bucket{le=\"30\"} 2\n"), "{}", text);
+    assert!(text.contains("r2d2_mongodb_checkout_wait_seconds_count 2\n"), "{}", text);
+}
+
+#[cfg(feature = "test-util")]
+mod with_server {
+    use r2d2::ManageConnection;
+    use r2d2_mongodb::test_util::{Fault, MockServer};
+    use r2d2_mongodb::{ConnectionOptions, MongodbConnectionManager, PoolMetrics};
+
+    fn host(server: &MockServer) -> String {
+        format!("host=\"127.0.0.1:{}\"", server.address().port())
+    }
+
+    #[test]
+    fn counts_auth_failures() {
+        let server = MockServer::start_with_user("root", "secret").unwrap();
+        let metrics = PoolMetrics::new();
+        let options = ConnectionOptions::builder()
+            .with_host("127.0.0.1", server.address().port())
+            .with_auth("root", "wrong")
+            .with_handshake(true)
+            .build();
+        let manager = MongodbConnectionManager::new(options).with_event_handler(Box::new(metrics.clone()));
+        assert!(manager.connect().is_err());
+
+        let pool = r2d2::Pool::builder().min_idle(Some(0)).build_unchecked(manager);
+        let text = metrics.render(&pool.state());
+        let line = format!("r2d2_mongodb_connect_failures_total{{{},kind=\"auth\"}} 1\n", host(&server));
+        assert!(text.contains(&line), "{}", text);
+    }
+
+    #[test]
+    fn counts_validation_failures_and_discards() {
+        let server = MockServer::start().unwrap();
+        let metrics = PoolMetrics::new();
+        let manager = MongodbConnectionManager::new_with_uri(&server.uri())
+            .unwrap()
+            .with_event_handler(Box::new(metrics.clone()));
+        let mut conn = manager.connect().unwrap();
+
+        let injected = Fault::CommandError {
+            code: 8000,
+            message: "injected".to_string(),
+        };
+        server.fail_command("listDatabases", injected, 1);
+        assert!(manager.is_valid(&mut conn).is_err());
+        // Retired by moving the manager to another database.
+        let mut options = (*manager.options()).clone();
+        options.db = "other".to_string();
+        manager.options_handle().update(options).unwrap();
+        assert!(manager.has_broken(&mut conn));
+
+        let pool = r2d2::Pool::builder().min_idle(Some(0)).build_unchecked(manager);
+        let text = metrics.render(&pool.state());
+        let validation = format!("r2d2_mongodb_validation_failures_total{{{}}} 1\n", host(&server));
+        let discarded = format!("r2d2_mongodb_discarded_connections_total{{{}}} 1\n", host(&server));
+        assert!(text.contains(&validation), "{}", text);
+        assert!(text.contains(&discarded), "{}", text);
+    }
+}
@@ -0,0 +1,67 @@
+extern crate r2d2_mongodb;
+
+use r2d2_mongodb::{Error, MongodbConnectionManager, PoolConfig};
+
+use std::time::Duration;
+
+#[test]
+fn defaults_without_pool_options() {
+    let config = PoolConfig::from_uri("mongodb://localhost/db").unwrap();
+    assert_eq!(config, PoolConfig::default());
+    assert!(!config.test_on_check_out);
+}
+
+#[test]
+fn maps_uri_pool_options() {
+    let config = PoolConfig::from_uri(
+        "mongodb://localhost/?maxPoolSize=20&minpoolsize=2&maxIdleTimeMS=60000&waitQueueTimeoutMS=500",
+    )
+    .unwrap();
+    assert_eq!(config.max_size, 20);
+    assert_eq!(config.min_idle, Some(2));
+    assert_eq!(config.idle_timeout, Some(Duration::from_secs(60)));
+    assert_eq!(config.connection_timeout, Duration::from_millis(500));
+}
+
+#[test]
+fn zero_idle_time_disables_idle_timeout() {
+    let config = PoolConfig::from_uri("mongodb://localhost/?maxIdleTimeMS=0").unwrap();
+    assert_eq!(config.idle_timeout, None);
+}
+
+#[test]
+fn min_pool_size_raises_the_default_max_size() {
+    let config = PoolConfig::from_uri("mongodb://localhost/?minPoolSize=20").unwrap();
+    assert_eq!(config.max_size, 20);
+    assert_eq!(config.min_idle, Some(20));
+
+    let config = PoolConfig::from_uri("mongodb://localhost/?minPoolSize=5").unwrap();
+    assert_eq!(config.max_size, PoolConfig::default().max_size);
+}
+
+#[test]
+fn rejects_invalid_pool_options() {
+    for uri in &[
+        "mongodb://localhost/?maxPoolSize=many",
+        "mongodb://localhost/?maxPoolSize=0",
+        "mongodb://localhost/?maxPoolSize=2&minPoolSize=3",
+        "mongodb://localhost/?waitQueueTimeoutMS=0",
+        "mongodb://localhost/?maxIdleTimeMS=-1",
+    ] {
+        match PoolConfig::from_uri(uri) {
+            Err(Error::Config(_)) => {}
+            other => panic!("{}: expected a config error, got {:?}", uri, other),
+        }
+    }
+}
+
+#[test]
+fn manager_and_builder_from_uri() {
+    let uri = "mongodb://localhost/db?maxPoolSize=7&minPoolSize=0";
+    let (manager, builder) = MongodbConnectionManager::new_with_uri_and_builder(uri).unwrap();
+    // Nothing is connected with minPoolSize=0
+    let pool = builder.build_unchecked(manager);
+    assert_eq!(pool.max_size(), 7);
+    assert_eq!(pool.min_idle(), Some(0));
+    assert_eq!(pool.state().connections, 0);
+}
@@ -0,0 +1,29 @@
+extern crate r2d2_mongodb;
+
+use r2d2_mongodb::{Error, RetryPolicy};
+
+use std::time::Duration;
+
+#[test]
+fn backoff_grows_with_jitter_up_to_the_maximum() {
+    let retry = RetryPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_millis(350),
+        ..RetryPolicy::default()
+    };
+    for _ in 0..100 {
+        let first = retry.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let third = retry.backoff(3);
+        assert!(third >= Duration::from_millis(175) && third <= Duration::from_millis(350));
+        assert!(retry.backoff(100) <= Duration::from_millis(350));
+    }
+}
+
+#[test]
+fn only_listed_errors_are_retryable() {
+    let retry = RetryPolicy::default();
+    assert!(!retry.is_retryable(&Error::Config("No host provided".to_string())));
+    let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+    assert!(retry.is_retryable(&Error::Connect(io.into())));
+}
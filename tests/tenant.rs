@@ -0,0 +1,139 @@
+extern crate r2d2_mongodb;
+
+use r2d2_mongodb::{ConnectionOptions, Error, MongodbConnectionManager, PoolConfig, TenantPools};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Barrier;
+use std::thread;
+use std::time::Duration;
+
+// Clients connect lazily and are not tested on check out, so no server is needed.
+fn tenants(max_size: u32) -> TenantPools {
+    let options = ConnectionOptions::builder().with_host("localhost", 27017).build();
+    let config = PoolConfig {
+        max_size,
+        min_idle: Some(0),
+        connection_timeout: Duration::from_millis(100),
+        test_on_check_out: false,
+        ..PoolConfig::default()
+    };
+    TenantPools::new(config.build(MongodbConnectionManager::new(options)).unwrap())
+}
+
+#[test]
+fn tracks_tenants_on_first_use() {
+    let tenants = tenants(4);
+    assert!(tenants.is_empty());
+
+    assert_eq!(tenants.get("tenant_a").unwrap().name(), "tenant_a");
+    tenants.get("tenant_a").unwrap();
+    assert_eq!(tenants.get("tenant_b").unwrap().name(), "tenant_b");
+    assert_eq!(tenants.len(), 2);
+    // Both tenants were served by the same pooled connection.
+    assert_eq!(tenants.pool().state().connections, 1);
+
+    assert!(tenants.remove("tenant_a"));
+    assert!(!tenants.remove("tenant_a"));
+    assert_eq!(tenants.len(), 1);
+}
+
+#[test]
+fn limits_connections_per_tenant() {
+    let tenants = tenants(8)
+        .with_max_connections(1)
+        .with_tenant_max_connections("big_customer", 2);
+
+    let held = tenants.get("trial").unwrap();
+    assert!(matches!(tenants.get("trial"), Err(Error::TenantLimit(ref db)) if db == "trial"));
+    let _other = tenants.get("other").unwrap();
+    drop(held);
+    let _held = tenants.get("trial").unwrap();
+
+    let _first = tenants.get("big_customer").unwrap();
+    let _second = tenants.get("big_customer").unwrap();
+    assert!(matches!(tenants.get("big_customer"), Err(Error::TenantLimit(_))));
+}
+
+#[test]
+fn waits_for_a_tenant_connection_to_be_released() {
+    let tenants = tenants(4).with_max_connections(1);
+    let held = tenants.get("trial").unwrap();
+
+    thread::scope(|scope| {
+        let waiter = scope.spawn(|| tenants.get("trial").map(|conn| conn.name().to_string()));
+        thread::sleep(Duration::from_millis(20));
+        drop(held);
+        assert_eq!(waiter.join().unwrap().unwrap(), "trial");
+    });
+}
+
+#[test]
+fn concurrent_first_requests_share_the_tenant_limit() {
+    let tenants = tenants(8).with_max_connections(1);
+    let start = Barrier::new(8);
+    let holding = AtomicUsize::new(0);
+    let most = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        for _ in 0..8 {
+            scope.spawn(|| {
+                start.wait();
+                if let Ok(_conn) = tenants.get("new_tenant") {
+                    let now = holding.fetch_add(1, Ordering::SeqCst) + 1;
+                    most.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    holding.fetch_sub(1, Ordering::SeqCst);
+                }
+            });
+        }
+    });
+    assert_eq!(most.load(Ordering::SeqCst), 1);
+    assert_eq!(tenants.len(), 1);
+}
+
+#[test]
+fn evicts_idle_tenants() {
+    let tenants = tenants(4).with_idle_timeout(Some(Duration::from_millis(50)));
+    tenants.get("idle").unwrap();
+    let _busy = tenants.get("busy").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    // Tenants holding connections are kept.
+    assert_eq!(tenants.evict_idle(), 1);
+    assert_eq!(tenants.len(), 1);
+
+    let tenants = tenants.with_idle_timeout(None);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(tenants.evict_idle(), 0);
+    assert_eq!(tenants.len(), 1);
+}
+
+#[test]
+fn rejects_invalid_database_names() {
+    let tenants = tenants(4);
+    for db in &["", "a.b", "a/b", "a b", "$a", &"a".repeat(64)] {
+        assert!(matches!(tenants.get(db), Err(Error::Config(_))), "{:?}", db);
+    }
+    assert!(tenants.is_empty());
+}
+
+#[test]
+#[cfg(feature = "test-util")]
+fn tenant_databases_keep_the_configured_defaults() {
+    use r2d2_mongodb::mongodb::options::ReadConcern;
+    use r2d2_mongodb::test_util::MockServer;
+
+    let server = MockServer::start().unwrap();
+    let mut options = ConnectionOptions::from_uri(&server.uri()).unwrap();
+    options.read_concern = Some(ReadConcern::majority());
+    let pool = PoolConfig {
+        min_idle: Some(0),
+        ..PoolConfig::default()
+    }
+    .build(MongodbConnectionManager::new(options))
+    .unwrap();
+    let tenants = TenantPools::new(pool);
+
+    let conn = tenants.get("tenant_a").unwrap();
+    assert_eq!(conn.name(), "tenant_a");
+    assert_eq!(conn.read_concern(), Some(&ReadConcern::majority()));
+}
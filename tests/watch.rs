@@ -0,0 +1,119 @@
+extern crate r2d2_mongodb;
+
+use r2d2_mongodb::{ConfigWatcher, ConnectionOptions, Error, MongodbConnectionManager};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn file(name: &str, contents: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("r2d2_mongodb_watch_test_{}", name));
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn manager() -> MongodbConnectionManager {
+    MongodbConnectionManager::new_with_uri("mongodb://old:27017/app").unwrap()
+}
+
+#[test]
+fn applies_a_changed_uri() {
+    let path = file("applies", "mongodb://new:27017/app\n");
+    let manager = manager();
+    let mut watcher = ConfigWatcher::new(&path, manager.options_handle());
+
+    assert!(watcher.check().unwrap());
+    assert_eq!(manager.options().hosts[0].hostname, "new");
+    assert!(!watcher.check().unwrap());
+
+    fs::write(&path, "mongodb://newer:27017,other:27018/app").unwrap();
+    assert!(watcher.check().unwrap());
+    assert_eq!(manager.options().hosts.len(), 2);
+}
+
+#[test]
+fn rejects_invalid_updates_and_keeps_the_old_options() {
+    let path = file("rejects", "mongodb://new:notaport/app");
+    let manager = manager();
+    let mut watcher = ConfigWatcher::new(&path, manager.options_handle());
+
+    assert!(matches!(watcher.check(), Err(Error::Parse(_))));
+    assert_eq!(manager.options().hosts[0].hostname, "old");
+    // Not retried until the file changes.
+    assert!(!watcher.check().unwrap());
+
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(watcher.check(), Err(Error::Config(_))));
+    assert_eq!(manager.options().hosts[0].hostname, "old");
+}
+
+#[test]
+fn reads_other_formats_with_a_parser() {
+    let path = file("parser", "new:27018");
+    let manager = manager();
+    let mut watcher = ConfigWatcher::new(&path, manager.options_handle()).with_parser(|contents| {
+        let (hostname, port) = contents.split_once(':').unwrap();
+        Ok(ConnectionOptions::builder()
+            .with_host(hostname, port.parse().unwrap())
+            .build())
+    });
+
+    assert!(watcher.check().unwrap());
+    assert_eq!(manager.options().hosts[0].port, 27018);
+
+    // Options without hosts fail validation.
+    let mut watcher = ConfigWatcher::new(&path, manager.options_handle())
+        .with_parser(|_| Ok(ConnectionOptions::builder().build()));
+    assert!(matches!(watcher.check(), Err(Error::Config(_))));
+    assert_eq!(manager.options().hosts[0].port, 27018);
+}
+
+#[test]
+fn spawned_watcher_reloads_until_dropped() {
+    let path = file("spawned", "mongodb://new:27017/app");
+    let manager = manager();
+    let guard = ConfigWatcher::new(&path, manager.options_handle())
+        .with_interval(Duration::from_millis(10))
+        .spawn();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while manager.options().hosts[0].hostname != "new" {
+        assert!(Instant::now() < deadline, "file was not applied");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    drop(guard);
+    thread::sleep(Duration::from_millis(50));
+    fs::write(&path, "mongodb://newer:27017/app").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(manager.options().hosts[0].hostname, "new");
+}
+
+#[test]
+fn spawned_watcher_reports_rejected_updates() {
+    let path = file("spawned_rejects", "mongodb://new:notaport/app");
+    let manager = manager();
+    let (errors, rejected) = mpsc::channel();
+    let _guard = ConfigWatcher::new(&path, manager.options_handle())
+        .with_interval(Duration::from_millis(10))
+        .with_error_handler(move |e| {
+            let _ = errors.send(e.to_string());
+        })
+        .spawn();
+
+    let e = rejected.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(e.starts_with("invalid connection string"), "{}", e);
+    assert_eq!(manager.options().hosts[0].hostname, "old");
+    // Reported once, not on every check of the unchanged file.
+    assert!(rejected.recv_timeout(Duration::from_millis(100)).is_err());
+
+    fs::write(&path, "mongodb://new:27017/app").unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while manager.options().hosts[0].hostname != "new" {
+        assert!(Instant::now() < deadline, "file was not applied");
+        thread::sleep(Duration::from_millis(10));
+    }
+}